@@ -0,0 +1,165 @@
+use std::collections::BTreeSet;
+
+use clap::Parser;
+use regex::Regex;
+use rootcause::bail;
+use thirtyfour::{By, WebDriver};
+
+use crate::{
+    command::{Ao3Opts, WebRunnable, plan::Ao3Plan},
+    driver::{DriverExts, archive, chapter_id_of},
+    exit::Failure,
+    forms::{chapter_form::ChapterForm, model::PostAction, work_form::WorkForm},
+    plan::{LiveWork, Target, plan},
+    utils::prompt,
+    *,
+};
+
+/// Makes exactly the changes `plan` shows, then records new chapter URLs.
+#[derive(Debug, Clone, Default, Parser)]
+pub struct Ao3Apply {
+    #[clap(flatten)]
+    pub plan: Ao3Plan,
+
+    /// Apply without asking first
+    #[clap(long, short = 'y')]
+    pub yes: bool,
+}
+
+impl WebRunnable for Ao3Apply {
+    async fn pre(&mut self, opts: &mut Ao3Opts) -> Result<()> {
+        self.plan.pre(opts).await
+    }
+
+    async fn run(self, driver: &mut WebDriver, opt: Ao3Opts) -> Result<()> {
+        opt.log_in(driver).await?;
+
+        let Ao3Plan {
+            fic: path,
+            mut loaded,
+            id,
+        } = self.plan;
+
+        let live = LiveWork::load(driver, id).await?;
+        let pseuds = opt.pseuds_for(&loaded)?;
+        let changes = plan(&loaded, &pseuds, &live).await?;
+        for change in &changes {
+            println_async!("{}", change);
+        }
+
+        let todo: Vec<_> = changes
+            .iter()
+            .filter(|c| c.target != Target::LiveOnly)
+            .collect();
+        if todo.is_empty() {
+            println_async!("Nothing to apply to work {}", id);
+            return Ok(());
+        }
+
+        if !self.yes && opt.general_options.non_interactive {
+            return Err(Failure::NeedsInteraction.report("Pass --yes to apply without asking"));
+        }
+        if !self.yes {
+            let yn = prompt("Apply these changes? [y/N] ").await?;
+            if !yn.trim().eq_ignore_ascii_case("y") {
+                println_async!("Nothing applied");
+                return Ok(());
+            }
+        }
+
+        if todo.iter().any(|c| c.target == Target::Work) {
+            println_async!("Updating work {}", id);
+            driver.ao3(format!("/works/{id}/edit")).await?;
+
+            let work_form = WorkForm::from(driver.find(By::Id("work-form")).await?);
+            work_form.fill_out(&loaded, &pseuds, opt.check_tags).await?;
+            work_form.submit(PostAction::Update).await?;
+
+            driver
+                .wait_for_saved(&Regex::new(&format!(r"^/works/{id}$")).unwrap(), "fic")
+                .await?;
+        }
+
+        let edited: BTreeSet<usize> = todo
+            .iter()
+            .filter_map(|c| match c.target {
+                Target::Chapter(i) => Some(i),
+                _ => None,
+            })
+            .collect();
+        for i in edited {
+            let url = &live.chapters[i].details.url;
+            let Some(chapter_id) = chapter_id_of(url) else {
+                bail!("Not a chapter URL: {:?}", url);
+            };
+            let Some((key, chapter)) = loaded.chapters.iter().nth(i) else {
+                continue;
+            };
+            let Some(file) = loaded.chapter_file(i) else {
+                bail!("Chapter {} has no text file", i + 1);
+            };
+            let html = loaded.render_file(file).await?;
+
+            println_async!("Updating chapter {}", i + 1);
+            driver.goto(format!("{url}/edit")).await?;
+
+            let chapter_form = ChapterForm::from(driver.find(By::Id("chapter-form")).await?);
+            chapter_form
+                .fill_out(chapter, loaded.meta.total_chapters, &html)
+                .await?;
+            chapter_form.submit(PostAction::Update).await?;
+
+            driver
+                .wait_for_saved(
+                    &Regex::new(&format!(r"^/works/{id}/chapters/{chapter_id}$")).unwrap(),
+                    &format!("chapters.{key}"),
+                )
+                .await?;
+        }
+
+        let added: Vec<String> = todo
+            .iter()
+            .filter_map(|c| match &c.target {
+                Target::NewChapter(key) => Some(key.clone()),
+                _ => None,
+            })
+            .collect();
+        for key in &added {
+            let chapter = &loaded.chapters[key];
+            let Some(file) = &chapter.file else {
+                bail!("Chapter {} has no text file", key);
+            };
+            let html = loaded.render_file(file).await?;
+
+            println_async!("Posting chapter {}", key);
+            driver.ao3(format!("/works/{id}/chapters/new")).await?;
+
+            let chapter_form = ChapterForm::from(driver.find(By::Id("chapter-form")).await?);
+            chapter_form
+                .fill_out(chapter, loaded.meta.total_chapters, &html)
+                .await?;
+            chapter_form.submit(PostAction::Post).await?;
+
+            let posted = driver
+                .wait_for_saved(
+                    &Regex::new(&format!(r"^/works/{id}/chapters/\d+$")).unwrap(),
+                    &format!("chapters.{key}"),
+                )
+                .await?;
+            let chapter_id = chapter_id_of(&posted).unwrap_or_default();
+
+            if let Some(chapter) = loaded.chapters.get_mut(key) {
+                chapter.url = format!("{}/works/{id}/chapters/{chapter_id}", archive());
+            }
+        }
+
+        if !added.is_empty() {
+            loaded.save_urls().await?;
+            println_async!("Recorded chapter URLs in {:?}", path);
+        }
+
+        println_async!("Applied {} change(s) to work {}", todo.len(), id);
+
+        Ok(())
+    }
+}
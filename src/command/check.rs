@@ -0,0 +1,31 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::{command::*, exit::Failure, validate::check_spec, *};
+
+#[derive(Debug, Clone, Parser)]
+pub struct CheckCommand {
+    file: PathBuf,
+}
+
+impl Runnable for CheckCommand {
+    async fn run(self, _opts: FicwrightOpts) -> Result<()> {
+        let (_, problems) = check_spec(&self.file).await?;
+
+        for problem in &problems {
+            eprintln_async!("{}", problem);
+        }
+
+        if !problems.is_empty() {
+            return Err(Failure::InvalidSpec.report(format!(
+                "{} problem(s) found in {:?}",
+                problems.len(),
+                self.file
+            )));
+        }
+
+        println_async!("{:?} is ready for AO3", self.file);
+        Ok(())
+    }
+}
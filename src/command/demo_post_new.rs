@@ -17,7 +17,7 @@ pub struct Ao3DemoPostNew {}
 
 impl WebRunnable for Ao3DemoPostNew {
     async fn run(self, driver: &mut thirtyfour::WebDriver, opt: Ao3Opts) -> Result<()> {
-        driver.add_cookies(&opt.get_cookies().await?).await?;
+        opt.log_in(driver).await?;
 
         driver.ao3("/works/new").await?;
 
@@ -1,43 +1,122 @@
-use crate::{command::*, config::*, driver::DriverExts, utils::*, *};
+use crate::{
+    command::*,
+    config::*,
+    driver::{DriverExts, poll_until},
+    exit::Failure,
+    forms::login_form::LoginForm,
+    secret::Credentials,
+    utils::*,
+    *,
+};
 use clap::Parser;
 use thirtyfour::prelude::*;
 
 #[derive(Debug, Clone, Parser)]
-pub struct Ao3Login {}
+pub struct Ao3Login {
+    /// Log in by hand even if the profile has a credential command
+    #[clap(long)]
+    pub manual: bool,
+
+    /// Log in afresh even if the saved session still works
+    #[clap(long)]
+    pub renew: bool,
+}
 
 impl WebRunnable for Ao3Login {
     async fn run(self, driver: &mut WebDriver, opt: Ao3Opts) -> Result<()> {
-        if let Ok(cookies) = opt.get_cookies().await {
-            driver.add_cookies(&cookies).await?;
-
-            let yn = prompt("Are you logged in? [Y/n]").await?;
+        let cookie_file = opt.cookie_file()?;
+        if !self.renew
+            && let Ok(cookies) = opt.get_cookies().await
+        {
+            if let Ok(usable) = cookies.usable().await {
+                driver.add_cookies(&usable).await?;
 
-            if yn.contains('n') || yn.contains('N') {
-                println_async!(
-                    "Deleting unusable cookie file: {}",
-                    opt.cookies.to_string_lossy()
-                );
-                tokio::fs::remove_file(&opt.cookies).await?;
+                if let Some(user) = driver.logged_in_user().await? {
+                    println_async!("Already logged in as {}", user);
+                    return Ok(());
+                }
             }
 
-            return Ok(());
-        } else {
-            driver.find(By::Id("login-dropdown")).await?.click().await?;
-            driver
-                .find(By::Id("user_session_login_small"))
-                .await?
-                .focus()
-                .await?;
-
-            prompt("Please log in... [Enter to continue]").await?;
+            println_async!(
+                "Deleting expired cookie file: {}",
+                cookie_file.to_string_lossy()
+            );
+            tokio::fs::remove_file(&cookie_file).await?;
         }
 
-        CookieConfig::new(driver.get_all_cookies().await?)
-            .save_to_file(&opt.cookies)
+        let credentials = if self.manual {
+            None
+        } else {
+            opt.credentials().await?
+        };
+        let user = match credentials {
+            Some(credentials) => log_in_with(driver, &credentials).await?,
+            None => log_in_by_hand(driver, &opt).await?,
+        };
+
+        opt.save_cookies(&CookieConfig::new(driver.get_cookie_records().await?))
             .await?;
 
-        println_async!("Cookies saved to {}", opt.cookies.to_string_lossy());
+        println_async!(
+            "Logged in as {}, cookies saved to {}",
+            user,
+            cookie_file.to_string_lossy()
+        );
 
         Ok(())
     }
 }
+
+async fn log_in_by_hand(driver: &WebDriver, opt: &Ao3Opts) -> Result<String> {
+    if !opt.general_options.interactive() {
+        return Err(Failure::NeedsInteraction.report("Logging in needs a person at a window"));
+    }
+
+    driver.find(By::Id("login-dropdown")).await?.click().await?;
+    driver
+        .find(By::Id("user_session_login_small"))
+        .await?
+        .focus()
+        .await?;
+
+    prompt("Please log in... [Enter to continue]").await?;
+
+    match driver.logged_in_user().await? {
+        Some(user) => Ok(user),
+        None => Err(Failure::NotLoggedIn.report("Still not logged in, no cookies saved")),
+    }
+}
+
+/// Submits the login form, then waits for AO3 to either greet the user or
+/// say why not, such as a wrong password or a locked account.
+async fn log_in_with(driver: &WebDriver, credentials: &Credentials) -> Result<String> {
+    println_async!("Logging in as {}", credentials.username);
+    driver.find(By::Id("login-dropdown")).await?.click().await?;
+    let form = driver
+        .find(By::XPath(
+            "//input[@id='user_session_login_small']/ancestor::form[1]",
+        ))
+        .await?;
+    LoginForm::from(form).log_in(credentials).await?;
+
+    let mut outcome = None;
+    poll_until(&driver.handle, async || {
+        if let Ok(Some(user)) = driver.logged_in_user().await {
+            outcome = Some(Ok(user));
+        } else if let Some(flash) = driver.find_all(By::Css(".flash.error")).await?.first() {
+            outcome = Some(Err(flash.text().await?));
+        }
+        Ok(outcome.is_some())
+    })
+    .await?;
+
+    match outcome {
+        Some(Ok(user)) => Ok(user),
+        Some(Err(reason)) => Err(Failure::NotLoggedIn.report(format!(
+            "AO3 refused to log in {}: {}",
+            credentials.username,
+            reason.trim()
+        ))),
+        None => Err(Failure::NotLoggedIn.report("AO3 never answered the login")),
+    }
+}
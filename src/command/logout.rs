@@ -11,16 +11,22 @@ pub struct Ao3Logout {
 
 impl WebRunnable for Ao3Logout {
     async fn run(self, driver: &mut WebDriver, opt: Ao3Opts) -> Result<()> {
-        driver.add_cookies(&opt.get_cookies().await?).await?;
+        let cookies = opt.get_cookies().await?;
 
-        driver
-            .find(By::Css("a[data-method=\"delete\"]"))
-            .await?
-            .click()
-            .await?;
+        // An expired session is as good as logged out already.
+        if let Ok(usable) = cookies.usable().await {
+            driver.add_cookies(&usable).await?;
+        }
+        if driver.logged_in_user().await?.is_some() {
+            driver
+                .find(By::Css("a[data-method=\"delete\"]"))
+                .await?
+                .click()
+                .await?;
+        }
 
         if !self.keep {
-            tokio::fs::remove_file(&opt.cookies).await?;
+            tokio::fs::remove_file(opt.cookie_file()?).await?;
         }
 
         Ok(())
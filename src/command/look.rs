@@ -1,15 +1,25 @@
 use clap::Parser;
 use thirtyfour::WebDriver;
 
-use crate::{command::*, driver::DriverExts, utils::*, *};
+use crate::{command::*, driver::DriverExts, exit::Failure, utils::*, *};
 
 #[derive(Debug, Clone, Copy, Parser)]
 pub struct Ao3Look;
 
 impl WebRunnable for Ao3Look {
+    async fn pre(&mut self, opts: &mut Ao3Opts) -> Result<()> {
+        if !opts.general_options.interactive() {
+            return Err(Failure::NeedsInteraction
+                .report("look only waits for a person at a browser window"));
+        }
+        Ok(())
+    }
+
     async fn run(self, driver: &mut WebDriver, opt: Ao3Opts) -> Result<()> {
-        if let Ok(cookies) = opt.get_cookies().await {
-            driver.add_cookies(&cookies).await?;
+        if let Ok(cookies) = opt.get_cookies().await
+            && let Ok(usable) = cookies.usable().await
+        {
+            driver.add_cookies(&usable).await?;
         }
 
         prompt("Waiting for user input...").await?;
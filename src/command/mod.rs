@@ -1,31 +1,57 @@
 use std::{
     os::fd::{AsRawFd, FromRawFd},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::Stdio,
-    time::Duration,
+    sync::Arc,
 };
 
 use crate::{
     Result,
-    command::{demo_post_new::Ao3DemoPostNew, post_new::Ao3PostNew},
-    config::CookieConfig,
-    driver::DriverExts,
+    command::{
+        apply::Ao3Apply, demo_post_new::Ao3DemoPostNew, plan::Ao3Plan,
+        post_chapter::Ao3PostChapter, post_new::Ao3PostNew, pull::Ao3Pull, update::Ao3Update,
+        update_chapter::Ao3UpdateChapter,
+    },
+    config::{CookieConfig, Fanfiction, FicwrightConfig, Profile, expand_home},
+    driver::{
+        AO3, Browser, DriverExts, set_archive, wait_for_driver, webdriver_config, work_id_of,
+    },
+    exit::Failure,
+    forms::model::PostAction,
+    println_async,
+    secret::{Credentials, Secret},
     utils::prompt,
+    validate::load_checked,
 };
-use clap::{Parser, Subcommand};
+use check::CheckCommand;
+use clap::{Args, Parser, Subcommand};
+use indexmap::IndexSet;
 use login::Ao3Login;
 use logout::Ao3Logout;
 use look::Ao3Look;
+use rootcause::{Report, bail, prelude::ResultExt};
 use template::{DebugTemplateCommand, TemplateCommand};
 use thirtyfour::prelude::*;
-use tokio::process::Child;
+use tokio::{process::Child, sync::OnceCell};
+use whoami::Ao3Whoami;
 
+pub mod apply;
+pub mod check;
 pub mod demo_post_new;
 pub mod login;
 pub mod logout;
 pub mod look;
+pub mod plan;
+pub mod post_chapter;
 pub mod post_new;
+pub mod pull;
 pub mod template;
+pub mod update;
+pub mod update_chapter;
+pub mod whoami;
+
+/// The cookie file when no profile is in use.
+pub const DEFAULT_COOKIES: &str = "~/.ao3.cookie";
 
 #[derive(Debug, Parser)]
 pub struct Ficwright {
@@ -43,13 +69,37 @@ impl Ficwright {
 
 #[derive(Debug, Clone, Default, Parser)]
 pub struct FicwrightOpts {
+    /// Append the WebDriver server's output to this file
+    #[clap(long, alias = "gecko-logfile")]
+    pub driver_logfile: Option<PathBuf>,
+
+    /// Run the browser without a window
     #[clap(long)]
-    pub gecko_logfile: Option<PathBuf>,
+    pub headless: bool,
+
+    /// Never prompt; fail with a distinct exit code where a person is needed
+    #[clap(long)]
+    pub non_interactive: bool,
+
+    #[clap(long, value_enum, default_value = "firefox")]
+    pub browser: Browser,
+
+    #[clap(long = "config", default_value = "~/.ficwright.toml")]
+    pub config_file: PathBuf,
+
+    #[clap(skip)]
+    pub config: FicwrightConfig,
 }
 
 impl FicwrightOpts {
-    async fn gecko_logfile(&self) -> Result<(Stdio, Stdio)> {
-        if let Some(f) = &self.gecko_logfile {
+    /// Whether a person can be asked to do something in the browser, which
+    /// needs a window as well as someone to answer.
+    pub fn interactive(&self) -> bool {
+        !self.headless && !self.non_interactive
+    }
+
+    async fn driver_logfile(&self) -> Result<(Stdio, Stdio)> {
+        if let Some(f) = &self.driver_logfile {
             let f = tokio::fs::File::options()
                 .append(true)
                 .create(true)
@@ -72,57 +122,106 @@ impl FicwrightOpts {
 
 #[derive(Debug, Clone, Subcommand)]
 enum Command {
-    Ao3(Ao3Command),
+    Ao3(Box<Ao3Command>),
     #[clap(flatten)]
     Local(LocalCommand),
 }
 
 impl Command {
-    pub async fn run(self, fw_opts: FicwrightOpts) -> Result<()> {
-        Ok(match self {
+    pub async fn run(self, mut fw_opts: FicwrightOpts) -> Result<()> {
+        match self {
+            // Only a browser run needs the config; local commands work
+            // without it, even when it is broken.
             Self::Ao3(mut ao3_command) => {
-                ao3_command.options.general_options = fw_opts;
-                ao3_command.command.pre(&ao3_command.options).await?;
+                fw_opts.config = FicwrightConfig::read_from_file(&fw_opts.config_file).await?;
+                let Ao3Command { options, command } = &mut *ao3_command;
+                options.general_options = fw_opts;
+                command.pre(options).await?;
+                set_archive(&options.archive_url()?)?;
 
-                let mut gecko = start_gecko(&ao3_command.options.general_options).await?;
+                let server = DriverServer::start(&ao3_command.options.general_options)
+                    .await
+                    .context(Failure::Driver)?;
 
-                let res = ao3_command.run().await;
+                let res = ao3_command.run(&server.url).await;
 
-                gecko.kill().await?;
-                gecko.wait().await?;
+                server.stop().await?;
 
-                res?;
+                res
             }
-            Self::Local(local_command) => local_command.run(fw_opts).await?,
-        })
+            Self::Local(local_command) => local_command.run(fw_opts).await,
+        }
     }
 }
 
-async fn start_gecko(opts: &FicwrightOpts) -> Result<Child> {
-    let (out, err) = opts.gecko_logfile().await?;
-    let child = tokio::process::Command::new("geckodriver")
-        .stdin(Stdio::null())
-        .stdout(out)
-        .stderr(err)
-        .spawn()?;
+/// The WebDriver server a run talks to: either one configured in
+/// `webdriver_url`, or a child of ours on a free port.
+struct DriverServer {
+    url: String,
+    child: Option<Child>,
+}
+
+impl DriverServer {
+    async fn start(opts: &FicwrightOpts) -> Result<Self> {
+        let waits = &opts.config.waits;
+        if let Some(url) = &opts.config.webdriver_url {
+            wait_for_driver(url, waits).await?;
+            return Ok(Self {
+                url: url.clone(),
+                child: None,
+            });
+        }
+
+        let port = std::net::TcpListener::bind(("127.0.0.1", 0))?
+            .local_addr()?
+            .port();
+
+        let (out, err) = opts.driver_logfile().await?;
+        let binary = opts.browser.driver_binary();
+        let mut child = tokio::process::Command::new(binary)
+            .arg(format!("--port={port}"))
+            .stdin(Stdio::null())
+            .stdout(out)
+            .stderr(err)
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let url = format!("http://127.0.0.1:{port}");
+        if let Err(e) = wait_for_driver(&url, waits).await {
+            if let Some(status) = child.try_wait()? {
+                bail!("{} exited early with {}", binary, status);
+            }
+            return Err(e);
+        }
 
-    tokio::time::sleep(Duration::from_millis(500)).await;
+        Ok(Self {
+            url,
+            child: Some(child),
+        })
+    }
 
-    Ok(child)
+    async fn stop(self) -> Result<()> {
+        if let Some(mut child) = self.child {
+            child.kill().await?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Subcommand)]
 enum LocalCommand {
     Template(TemplateCommand),
     DebugTemplate(DebugTemplateCommand),
+    Check(CheckCommand),
 }
 
 impl Runnable for LocalCommand {
     async fn run(self, opts: FicwrightOpts) -> Result<()> {
-        Ok(match self {
-            Self::Template(template) => template.run(opts).await?,
-            Self::DebugTemplate(debug) => debug.run(opts).await?,
-        })
+        match self {
+            Self::Template(template) => template.run(opts).await,
+            Self::DebugTemplate(debug) => debug.run(opts).await,
+            Self::Check(check) => check.run(opts).await,
+        }
     }
 }
 
@@ -139,22 +238,32 @@ struct Ao3Command {
 }
 
 impl Ao3Command {
-    pub async fn run(self) -> Result<()> {
-        let mut capabilities = DesiredCapabilities::firefox();
-        capabilities.unset_headless()?;
-        let mut driver = WebDriver::new("http://localhost:4444", capabilities).await?;
-
-        driver.ao3("").await?;
-
-        self.command.run(&mut driver, self.options).await?;
+    pub async fn run(self, webdriver_url: &str) -> Result<()> {
+        let opts = &self.options.general_options;
+        let headless = opts.headless;
+        let interactive = opts.interactive();
+        let capabilities = opts.browser.capabilities(headless)?;
+        let config = webdriver_config(&opts.config.waits)?;
+        let mut driver = WebDriver::new_with_config(webdriver_url, capabilities, config)
+            .await
+            .context(Failure::Driver)?;
+
+        let res = async {
+            driver.ao3("").await?;
+            self.command.run(&mut driver, self.options).await
+        }
+        .await;
 
-        driver.minimize_window().await?;
+        // Without a window, or a person, nobody looks at the browser before it quits.
+        if res.is_ok() && interactive {
+            driver.minimize_window().await?;
 
-        prompt("\n\nEnter to continue...").await?;
+            prompt("\n\nEnter to continue...").await?;
+        }
 
         driver.quit().await?;
 
-        Ok(())
+        res
     }
 }
 
@@ -163,18 +272,32 @@ enum Ao3Script {
     Login(Ao3Login),
     Logout(Ao3Logout),
     Look(Ao3Look),
+    Whoami(Ao3Whoami),
     DemoPostNew(Ao3DemoPostNew),
     PostNew(Ao3PostNew),
+    Pull(Ao3Pull),
+    Update(Ao3Update),
+    PostChapter(Ao3PostChapter),
+    UpdateChapter(Ao3UpdateChapter),
+    Plan(Ao3Plan),
+    Apply(Ao3Apply),
 }
 
 impl WebRunnable for Ao3Script {
-    async fn pre(&mut self, opts: &Ao3Opts) -> Result<()> {
+    async fn pre(&mut self, opts: &mut Ao3Opts) -> Result<()> {
         match self {
             Self::Login(ao3_login) => ao3_login.pre(opts).await,
             Self::Logout(ao3_logout) => ao3_logout.pre(opts).await,
             Self::Look(ao3_look) => ao3_look.pre(opts).await,
+            Self::Whoami(ao3_whoami) => ao3_whoami.pre(opts).await,
             Self::DemoPostNew(ao3_demo) => ao3_demo.pre(opts).await,
             Self::PostNew(ao3_post_new) => ao3_post_new.pre(opts).await,
+            Self::Pull(ao3_pull) => ao3_pull.pre(opts).await,
+            Self::Update(ao3_update) => ao3_update.pre(opts).await,
+            Self::PostChapter(ao3_post_chapter) => ao3_post_chapter.pre(opts).await,
+            Self::UpdateChapter(ao3_update_chapter) => ao3_update_chapter.pre(opts).await,
+            Self::Plan(ao3_plan) => ao3_plan.pre(opts).await,
+            Self::Apply(ao3_apply) => ao3_apply.pre(opts).await,
         }
     }
     async fn run(self, driver: &mut WebDriver, opt: Ao3Opts) -> Result<()> {
@@ -182,30 +305,197 @@ impl WebRunnable for Ao3Script {
             Self::Login(ao3_login) => ao3_login.run(driver, opt).await,
             Self::Logout(ao3_logout) => ao3_logout.run(driver, opt).await,
             Self::Look(ao3_look) => ao3_look.run(driver, opt).await,
+            Self::Whoami(ao3_whoami) => ao3_whoami.run(driver, opt).await,
             Self::DemoPostNew(ao3_post_new) => ao3_post_new.run(driver, opt).await,
             Self::PostNew(ao3_post_new) => ao3_post_new.run(driver, opt).await,
+            Self::Pull(ao3_pull) => ao3_pull.run(driver, opt).await,
+            Self::Update(ao3_update) => ao3_update.run(driver, opt).await,
+            Self::PostChapter(ao3_post_chapter) => ao3_post_chapter.run(driver, opt).await,
+            Self::UpdateChapter(ao3_update_chapter) => ao3_update_chapter.run(driver, opt).await,
+            Self::Plan(ao3_plan) => ao3_plan.run(driver, opt).await,
+            Self::Apply(ao3_apply) => ao3_apply.run(driver, opt).await,
+        }
+    }
+}
+
+/// How a new work or chapter is submitted.
+#[derive(Debug, Clone, Copy, Default, Args)]
+pub struct PostFlags {
+    /// Save as a draft instead of posting
+    #[clap(long)]
+    pub draft: bool,
+
+    /// Stop at AO3's preview
+    #[clap(long, conflicts_with = "draft")]
+    pub preview: bool,
+}
+
+impl PostFlags {
+    pub fn action(self) -> PostAction {
+        if self.preview {
+            PostAction::Preview
+        } else if self.draft {
+            PostAction::Draft
+        } else {
+            PostAction::Post
         }
     }
 }
 
 #[derive(Debug, Clone, Parser)]
 pub struct Ao3Opts {
+    /// Cookie file to use instead of the profile's
     #[clap(long, short = 'c')]
-    #[clap(default_value = "~/.ao3.cookie")]
-    pub cookies: PathBuf,
+    pub cookies: Option<PathBuf>,
+
+    /// Account profile from the config file
+    #[clap(long, short = 'p')]
+    pub profile: Option<String>,
+
+    /// Look up each tag AO3 does not suggest on its public tag page; that
+    /// is one request per tag, made outside the browser and not logged in
+    #[clap(long)]
+    pub check_tags: bool,
 
     #[clap(skip)]
     pub general_options: FicwrightOpts,
+
+    /// Asked for at most once a run.
+    #[clap(skip)]
+    pub cookie_secret: Arc<OnceCell<Option<Secret>>>,
 }
 
 impl Ao3Opts {
+    /// The profile asked for, or the configured default.
+    pub fn profile(&self) -> Result<Option<(&str, &Profile)>> {
+        let config = &self.general_options.config;
+        let Some(name) = self.profile.as_ref().or(config.default_profile.as_ref()) else {
+            return Ok(None);
+        };
+        match config.profiles.get_key_value(name) {
+            Some((name, profile)) => Ok(Some((name, profile))),
+            None => bail!(
+                "No profile {:?} in {:?}",
+                name,
+                self.general_options.config_file
+            ),
+        }
+    }
+
+    /// The profile's archive, or the configured one, or AO3.
+    pub fn archive_url(&self) -> Result<String> {
+        let profile = self.profile()?.and_then(|(_, p)| p.archive_url.as_ref());
+        let config = self.general_options.config.archive_url.as_ref();
+        Ok(profile.or(config).map_or(AO3, String::as_str).to_string())
+    }
+
+    /// The cookie file given with `--cookies`, or the profile's.
+    pub fn cookie_file(&self) -> Result<PathBuf> {
+        let file = match (&self.cookies, self.profile()?) {
+            (Some(file), _) => file.clone(),
+            (None, Some((_, profile))) => profile.cookies.clone(),
+            (None, None) => PathBuf::from(DEFAULT_COOKIES),
+        };
+        expand_home(&file)
+    }
+
+    /// Switches to the profile `fic` must be posted from.
+    pub fn use_profile_of(&mut self, fic: &Fanfiction) -> Result<()> {
+        if let Some(wanted) = &fic.meta.profile {
+            match &self.profile {
+                Some(given) if given != wanted => bail!(
+                    "{:?} must be posted from profile {:?}, not {:?}",
+                    fic.path,
+                    wanted,
+                    given
+                ),
+                _ => self.profile = Some(wanted.clone()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the checked spec of a posted work, switching to its profile,
+    /// along with the work's ID.
+    pub async fn load_posted(&mut self, path: &Path) -> Result<(Fanfiction, u64)> {
+        println_async!("Loading fanfic specification {:?}", path);
+        let fic = load_checked(path).await?;
+        self.use_profile_of(&fic)?;
+
+        let url = &fic.fic.url;
+        if url.is_empty() {
+            bail!("{:?} has no work URL, post it with post-new first", path);
+        }
+        let Some(id) = work_id_of(url) else {
+            bail!("Not a work URL: {:?}", url);
+        };
+        Ok((fic, id))
+    }
+
+    /// The pseuds `fic` is posted under: its own, or else the profile's.
+    pub fn pseuds_for(&self, fic: &Fanfiction) -> Result<IndexSet<String>> {
+        if !fic.fic.author_pseud.is_empty() {
+            return Ok(fic.fic.author_pseud.clone());
+        }
+        let profile = self.profile()?.and_then(|(_, p)| p.pseud.clone());
+        Ok(profile.into_iter().collect())
+    }
+
+    /// The profile's login details, if it can log in without a person.
+    pub async fn credentials(&self) -> Result<Option<Credentials>> {
+        let Some((_, profile)) = self.profile()? else {
+            return Ok(None);
+        };
+        let Some(command) = &profile.credential_command else {
+            return Ok(None);
+        };
+        let credentials = Credentials::from_command(command, profile.username.as_deref()).await?;
+        Ok(Some(credentials))
+    }
+
+    /// The key for the cookie file, if the config asks for encryption.
+    pub async fn cookie_secret(&self) -> Result<Option<&Secret>> {
+        let opts = &self.general_options;
+        let secret = self
+            .cookie_secret
+            .get_or_try_init(async || match &opts.config.cookie_key {
+                Some(key) => Ok::<_, Report>(Some(key.secret(!opts.non_interactive).await?)),
+                None => Ok(None),
+            })
+            .await?;
+        Ok(secret.as_ref())
+    }
+
     pub async fn get_cookies(&self) -> Result<CookieConfig> {
-        CookieConfig::read_from_file(&self.cookies).await
+        CookieConfig::read_from_file(&self.cookie_file()?, self.cookie_secret().await?).await
+    }
+
+    pub async fn save_cookies(&self, cookies: &CookieConfig) -> Result<()> {
+        cookies
+            .save_to_file(&self.cookie_file()?, self.cookie_secret().await?)
+            .await
+    }
+
+    /// Restores the saved session and checks that AO3 still accepts it,
+    /// returning the username.
+    pub async fn log_in(&self, driver: &WebDriver) -> Result<String> {
+        let cookies = self
+            .get_cookies()
+            .await
+            .context(Failure::NotLoggedIn)
+            .attach("Run `ficwright ao3 login` first")?;
+        driver.add_cookies(&cookies.usable().await?).await?;
+
+        match driver.logged_in_user().await? {
+            Some(user) => Ok(user),
+            None => Err(Failure::NotLoggedIn
+                .report("The AO3 session has expired, run `ficwright ao3 login` again")),
+        }
     }
 }
 
 pub trait WebRunnable {
-    fn pre(&mut self, opts: &Ao3Opts) -> impl Future<Output = Result<()>> {
+    fn pre(&mut self, opts: &mut Ao3Opts) -> impl Future<Output = Result<()>> {
         let _ = opts;
         async { Ok(()) }
     }
@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::{
+    command::{Ao3Opts, WebRunnable},
+    config::Fanfiction,
+    driver::DriverExts,
+    plan::{LiveWork, plan},
+    *,
+};
+
+/// Shows how a posted work differs from its spec, without changing anything.
+#[derive(Debug, Clone, Default, Parser)]
+pub struct Ao3Plan {
+    pub fic: PathBuf,
+
+    #[clap(skip)]
+    pub loaded: Fanfiction,
+
+    #[clap(skip)]
+    pub id: u64,
+}
+
+impl WebRunnable for Ao3Plan {
+    async fn pre(&mut self, opts: &mut Ao3Opts) -> Result<()> {
+        (self.loaded, self.id) = opts.load_posted(&self.fic).await?;
+        Ok(())
+    }
+
+    async fn run(self, driver: &mut thirtyfour::WebDriver, opt: Ao3Opts) -> Result<()> {
+        opt.log_in(driver).await?;
+
+        let live = LiveWork::load(driver, self.id).await?;
+        let pseuds = opt.pseuds_for(&self.loaded)?;
+        let changes = plan(&self.loaded, &pseuds, &live).await?;
+
+        if changes.is_empty() {
+            println_async!("Work {} matches {:?}", self.id, self.fic);
+        }
+        for change in &changes {
+            println_async!("{}", change);
+        }
+
+        Ok(())
+    }
+}
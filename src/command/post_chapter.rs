@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use regex::Regex;
+use rootcause::bail;
+use thirtyfour::By;
+
+use crate::{
+    command::{Ao3Opts, PostFlags, WebRunnable},
+    config::Fanfiction,
+    driver::{DriverExts, archive, chapter_id_of},
+    forms::{chapter_form::ChapterForm, model::PostAction},
+    *,
+};
+
+/// Posts the next chapter of the spec that has no URL recorded yet.
+#[derive(Debug, Clone, Default, Parser)]
+pub struct Ao3PostChapter {
+    pub fic: PathBuf,
+
+    #[clap(flatten)]
+    pub post: PostFlags,
+
+    #[clap(skip)]
+    pub loaded: Fanfiction,
+
+    #[clap(skip)]
+    pub id: u64,
+}
+
+impl WebRunnable for Ao3PostChapter {
+    async fn pre(&mut self, opts: &mut Ao3Opts) -> Result<()> {
+        (self.loaded, self.id) = opts.load_posted(&self.fic).await?;
+
+        if self.loaded.next_unposted().is_none() {
+            bail!("Every chapter of {:?} is already posted", self.fic);
+        }
+        Ok(())
+    }
+
+    async fn run(mut self, driver: &mut thirtyfour::WebDriver, opt: Ao3Opts) -> Result<()> {
+        opt.log_in(driver).await?;
+
+        let id = self.id;
+        let Some((key, chapter)) = self.loaded.next_unposted() else {
+            bail!("Every chapter of {:?} is already posted", self.fic);
+        };
+        let key = key.clone();
+        let Some(file) = &chapter.file else {
+            bail!("Chapter {} has no text file", key);
+        };
+        let html = self.loaded.render_file(file).await?;
+
+        driver.ao3(format!("/works/{id}/chapters/new")).await?;
+
+        let chapter_form = ChapterForm::from(driver.find(By::Id("chapter-form")).await?);
+        chapter_form
+            .fill_out(chapter, self.loaded.meta.total_chapters, &html)
+            .await?;
+        chapter_form.submit(self.post.action()).await?;
+
+        let path = driver
+            .wait_for_saved(
+                &Regex::new(&format!(r"^/works/{id}/chapters/\d+")).unwrap(),
+                &format!("chapters.{key}"),
+            )
+            .await?;
+        if self.post.action() == PostAction::Preview {
+            println_async!("Previewing chapter {key} of work {id}");
+            return Ok(());
+        }
+
+        let chapter_id = chapter_id_of(&path).unwrap_or_default();
+        let url = format!("{}/works/{id}/chapters/{chapter_id}", archive());
+
+        println_async!("Chapter {key}: {url}");
+
+        if let Some(chapter) = self.loaded.chapters.get_mut(&key) {
+            chapter.url = url;
+        }
+        self.loaded.save_urls().await?;
+
+        println_async!("Recorded chapter URL in {:?}", self.fic);
+
+        Ok(())
+    }
+}
@@ -1,48 +1,84 @@
-use std::{path::PathBuf, time::Duration};
+use std::path::PathBuf;
 
 use clap::Parser;
+use regex::Regex;
 use thirtyfour::By;
 
 use crate::{
-    command::{Ao3Opts, WebRunnable},
+    command::{Ao3Opts, PostFlags, WebRunnable},
     config::Fanfiction,
-    driver::DriverExts,
-    forms::{
-        model::{AgeRating, FicCategory},
-        work_form::WorkForm,
-    },
-    utils::prompt,
+    driver::{DriverExts, archive, work_id},
+    exit::Failure,
+    forms::{model::PostAction, work_form::WorkForm},
+    validate::load_checked,
     *,
 };
 
+/// Posts the spec as a new work and records its URL.
 #[derive(Debug, Clone, Default, Parser)]
 pub struct Ao3PostNew {
     pub fic: PathBuf,
 
+    #[clap(flatten)]
+    pub post: PostFlags,
+
+    /// Post a new work even though the spec already records one
     #[clap(long)]
-    pub draft: bool,
+    pub force: bool,
 
     #[clap(skip)]
     pub loaded: Fanfiction,
 }
 
 impl WebRunnable for Ao3PostNew {
-    async fn pre(&mut self, _opts: &Ao3Opts) -> Result<()> {
+    async fn pre(&mut self, opts: &mut Ao3Opts) -> Result<()> {
         println_async!("Loading fanfic specification {:?}", self.fic);
-        self.loaded = Fanfiction::load(&self.fic).await?;
+        self.loaded = load_checked(&self.fic).await?;
+        opts.use_profile_of(&self.loaded)?;
+
+        let url = &self.loaded.fic.url;
+        if !url.is_empty() && !self.force {
+            return Err(Failure::InvalidSpec.report(format!(
+                "{:?} is already posted as {}, use `ao3 update` or pass --force",
+                self.fic, url
+            )));
+        }
         Ok(())
     }
 
-    async fn run(self, driver: &mut thirtyfour::WebDriver, opt: Ao3Opts) -> Result<()> {
-        driver.add_cookies(&opt.get_cookies().await?).await?;
+    async fn run(mut self, driver: &mut thirtyfour::WebDriver, opt: Ao3Opts) -> Result<()> {
+        opt.log_in(driver).await?;
 
         driver.ao3("/works/new").await?;
 
         let work_form = WorkForm::from(driver.find(By::Id("work-form")).await?);
 
-        work_form.fill_out(&self.loaded).await?;
+        let pseuds = opt.pseuds_for(&self.loaded)?;
+        work_form
+            .fill_out(&self.loaded, &pseuds, opt.check_tags)
+            .await?;
+
+        work_form.submit(self.post.action()).await?;
+
+        let path = driver
+            .wait_for_saved(&Regex::new(r"^/works/\d+").unwrap(), "fic")
+            .await?;
+        let id = work_id(&path).unwrap_or_default();
+        let url = format!("{}/works/{id}", archive());
+
+        println_async!("Work {id}: {url}");
+
+        self.loaded.fic.url = url;
+
+        if self.post.action() != PostAction::Preview
+            && let Some(first) = self.loaded.chapters.values_mut().next()
+            && let Some(path) = driver.chapter_paths(id).await?.first()
+        {
+            first.url = format!("{}{path}", archive());
+        }
+        self.loaded.save_urls().await?;
 
-        tokio::time::sleep(Duration::from_millis(5000)).await;
+        println_async!("Recorded work URL in {:?}", self.fic);
 
         Ok(())
     }
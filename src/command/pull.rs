@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use rootcause::bail;
+
+use crate::{
+    command::{Ao3Opts, WebRunnable},
+    config::Fanfiction,
+    driver::{DriverExts, work_id_of},
+    plan::LiveWork,
+    *,
+};
+
+/// Imports a posted work into a new spec, with one HTML file per chapter.
+#[derive(Debug, Clone, Default, Parser)]
+pub struct Ao3Pull {
+    /// The work's URL or ID
+    pub work: String,
+
+    pub fic: PathBuf,
+
+    /// Overwrite an existing spec and chapter files
+    #[clap(long)]
+    pub force: bool,
+
+    #[clap(skip)]
+    pub id: u64,
+}
+
+impl Ao3Pull {
+    /// The name of the text file of chapter `n`, next to the spec.
+    fn chapter_file(&self, n: Option<usize>, width: usize) -> PathBuf {
+        let stem = self.fic.file_stem().unwrap_or_default().to_string_lossy();
+        match n {
+            Some(n) => format!("{stem}-{n:0width$}.html").into(),
+            None => format!("{stem}.html").into(),
+        }
+    }
+}
+
+impl WebRunnable for Ao3Pull {
+    async fn pre(&mut self, _opts: &mut Ao3Opts) -> Result<()> {
+        let Some(id) = work_id_of(&self.work) else {
+            bail!("Not a work URL or ID: {:?}", self.work);
+        };
+        self.id = id;
+
+        if !self.force && tokio::fs::try_exists(&self.fic).await? {
+            bail!(
+                "{:?} already exists, pass --force to overwrite it",
+                self.fic
+            );
+        }
+        Ok(())
+    }
+
+    async fn run(self, driver: &mut thirtyfour::WebDriver, opt: Ao3Opts) -> Result<()> {
+        opt.log_in(driver).await?;
+
+        let id = self.id;
+        let LiveWork {
+            mut fic, chapters, ..
+        } = LiveWork::load(driver, id).await?;
+        fic.path = self.fic.clone();
+        // Later updates must come from the account the work was pulled from.
+        fic.meta.profile = opt.profile()?.map(|(name, _)| name.to_string());
+
+        let count = chapters.len() as u64;
+        if fic.meta.total_chapters != 0 && fic.meta.total_chapters < count {
+            fic.meta.total_chapters = count;
+        }
+        let single = count == 1 && fic.meta.total_chapters == 1;
+        // Chapter keys sort as strings, so they are all padded alike.
+        let width = count.to_string().len().max(2);
+
+        let files: Vec<_> = (1..=chapters.len())
+            .map(|n| self.chapter_file((!single).then_some(n), width))
+            .collect();
+        if !self.force {
+            for file in &files {
+                if tokio::fs::try_exists(fic.resolve_file(file)).await? {
+                    bail!("{:?} already exists, pass --force to overwrite it", file);
+                }
+            }
+        }
+
+        for ((i, chapter), file) in chapters.into_iter().enumerate().zip(files) {
+            tokio::fs::write(fic.resolve_file(&file), &chapter.html).await?;
+
+            if single {
+                fic.fic.file = Some(file);
+            } else {
+                let mut details = chapter.details;
+                details.file = Some(file);
+                fic.chapters.insert(format!("{:0width$}", i + 1), details);
+            }
+        }
+
+        fic.save().await?;
+
+        println_async!("Pulled work {id} into {:?}", self.fic);
+
+        Ok(())
+    }
+}
@@ -23,7 +23,7 @@ impl Runnable for TemplateCommand {
                     start_note: Some("".into()),
                     end_note: Some("".into()),
                     summary: Some("Presented without summary".into()),
-                    author_pseud: None,
+                    author_pseud: ix_set![],
                     co_authors: ix_set![],
                 },
                 tags: Default::default(),
@@ -33,6 +33,7 @@ impl Runnable for TemplateCommand {
                     "02".to_string() => Default::default(),
                 },
                 remix: None,
+                path: Default::default(),
             })?,
         )
         .await?;
@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use regex::Regex;
+use thirtyfour::By;
+
+use crate::{
+    command::{Ao3Opts, WebRunnable},
+    config::Fanfiction,
+    driver::DriverExts,
+    forms::{model::PostAction, work_form::WorkForm},
+    *,
+};
+
+/// Brings an already posted work in line with its spec.
+#[derive(Debug, Clone, Default, Parser)]
+pub struct Ao3Update {
+    pub fic: PathBuf,
+
+    /// Stop at AO3's preview of the changes
+    #[clap(long)]
+    pub preview: bool,
+
+    #[clap(skip)]
+    pub loaded: Fanfiction,
+
+    #[clap(skip)]
+    pub id: u64,
+}
+
+impl WebRunnable for Ao3Update {
+    async fn pre(&mut self, opts: &mut Ao3Opts) -> Result<()> {
+        (self.loaded, self.id) = opts.load_posted(&self.fic).await?;
+        Ok(())
+    }
+
+    async fn run(self, driver: &mut thirtyfour::WebDriver, opt: Ao3Opts) -> Result<()> {
+        opt.log_in(driver).await?;
+
+        let id = self.id;
+        driver.ao3(format!("/works/{id}/edit")).await?;
+
+        let work_form = WorkForm::from(driver.find(By::Id("work-form")).await?);
+
+        let pseuds = opt.pseuds_for(&self.loaded)?;
+        work_form
+            .fill_out(&self.loaded, &pseuds, opt.check_tags)
+            .await?;
+
+        let action = if self.preview {
+            PostAction::Preview
+        } else {
+            PostAction::Update
+        };
+        work_form.submit(action).await?;
+
+        driver
+            .wait_for_saved(
+                &Regex::new(&format!(r"^/works/{id}(/preview)?$")).unwrap(),
+                "fic",
+            )
+            .await?;
+
+        println_async!("Updated work {id}: {}", self.loaded.fic.url);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use regex::Regex;
+use rootcause::bail;
+use thirtyfour::By;
+
+use crate::{
+    command::{Ao3Opts, WebRunnable},
+    config::Fanfiction,
+    driver::{DriverExts, chapter_id_of, work_id_of},
+    forms::{chapter_form::ChapterForm, model::PostAction},
+    validate::load_checked,
+    *,
+};
+
+/// Brings an already posted chapter in line with its spec.
+#[derive(Debug, Clone, Default, Parser)]
+pub struct Ao3UpdateChapter {
+    pub fic: PathBuf,
+
+    /// The chapter's key in the spec, like "02"
+    pub chapter: String,
+
+    /// Stop at AO3's preview of the changes
+    #[clap(long)]
+    pub preview: bool,
+
+    #[clap(skip)]
+    pub loaded: Fanfiction,
+
+    #[clap(skip)]
+    pub ids: (u64, u64),
+
+    /// Where the chapter is among the spec's chapters.
+    #[clap(skip)]
+    pub index: usize,
+}
+
+impl WebRunnable for Ao3UpdateChapter {
+    async fn pre(&mut self, opts: &mut Ao3Opts) -> Result<()> {
+        println_async!("Loading fanfic specification {:?}", self.fic);
+        self.loaded = load_checked(&self.fic).await?;
+        opts.use_profile_of(&self.loaded)?;
+
+        let Some(index) = self.loaded.chapters.keys().position(|k| *k == self.chapter) else {
+            bail!("{:?} has no chapter {:?}", self.fic, self.chapter);
+        };
+        let chapter = &self.loaded.chapters[&self.chapter];
+        if self.loaded.chapter_file(index).is_none() {
+            bail!("Chapter {} has no text file", self.chapter);
+        }
+        let (Some(work), Some(id)) = (work_id_of(&chapter.url), chapter_id_of(&chapter.url)) else {
+            bail!(
+                "Chapter {} has no chapter URL, post it with post-chapter first",
+                self.chapter
+            );
+        };
+        self.ids = (work, id);
+        self.index = index;
+        Ok(())
+    }
+
+    async fn run(self, driver: &mut thirtyfour::WebDriver, opt: Ao3Opts) -> Result<()> {
+        opt.log_in(driver).await?;
+
+        let (work, id) = self.ids;
+        let chapter = &self.loaded.chapters[&self.chapter];
+        let html = match self.loaded.chapter_file(self.index) {
+            Some(file) => self.loaded.render_file(file).await?,
+            None => bail!("Chapter {} has no text file", self.chapter),
+        };
+
+        driver
+            .ao3(format!("/works/{work}/chapters/{id}/edit"))
+            .await?;
+
+        let chapter_form = ChapterForm::from(driver.find(By::Id("chapter-form")).await?);
+        chapter_form
+            .fill_out(chapter, self.loaded.meta.total_chapters, &html)
+            .await?;
+
+        let action = if self.preview {
+            PostAction::Preview
+        } else {
+            PostAction::Update
+        };
+        chapter_form.submit(action).await?;
+
+        driver
+            .wait_for_saved(
+                &Regex::new(&format!(r"^/works/{work}/chapters/{id}(/preview)?$")).unwrap(),
+                &format!("chapters.{}", self.chapter),
+            )
+            .await?;
+
+        println_async!("Updated chapter {}: {}", self.chapter, chapter.url);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,21 @@
+use clap::Parser;
+use thirtyfour::WebDriver;
+
+use crate::{command::*, driver::DriverExts, *};
+
+/// Shows who the saved session is logged in as, and their pseuds.
+#[derive(Debug, Clone, Copy, Parser)]
+pub struct Ao3Whoami;
+
+impl WebRunnable for Ao3Whoami {
+    async fn run(self, driver: &mut WebDriver, opt: Ao3Opts) -> Result<()> {
+        let user = opt.log_in(driver).await?;
+
+        println_async!("{}", user);
+        for pseud in driver.pseuds(&user).await? {
+            println_async!("  {}", pseud);
+        }
+
+        Ok(())
+    }
+}
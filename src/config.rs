@@ -1,15 +1,22 @@
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
     path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use clap::Parser;
 use indexmap::IndexSet;
 use rootcause::bail;
 use serde::{Deserialize, Serialize};
-use thirtyfour::Cookie;
 
-use crate::{Result, forms::model::*, println_async, tree_set};
+use crate::{
+    Result, eprintln_async,
+    exit::Failure,
+    forms::model::*,
+    println_async,
+    secret::{CookieKey, Secret, is_encrypted, read_private, write_private},
+    tree_set,
+};
 
 #[derive(Debug, Clone, Serialize, Default, Deserialize)]
 pub struct Fanfiction {
@@ -19,11 +26,91 @@ pub struct Fanfiction {
     pub remix: Option<FicRemix>,
     #[serde(default)]
     pub chapters: BTreeMap<String, FicDetails>,
+    #[serde(skip)]
+    pub path: PathBuf,
 }
 
 impl Fanfiction {
     pub async fn load(path: &Path) -> Result<Self> {
-        Ok(toml::from_str(&tokio::fs::read_to_string(path).await?)?)
+        let mut fic: Self = toml::from_str(&tokio::fs::read_to_string(path).await?)?;
+        fic.path = path.to_path_buf();
+        Ok(fic)
+    }
+
+    pub async fn save(&self) -> Result<()> {
+        tokio::fs::write(&self.path, toml::to_string_pretty(self)?).await?;
+        Ok(())
+    }
+
+    /// Writes the work and chapter URLs into the spec file, leaving the
+    /// rest of it as its author wrote it.
+    pub async fn save_urls(&self) -> Result<()> {
+        let spec = tokio::fs::read_to_string(&self.path).await?;
+        tokio::fs::write(&self.path, self.with_urls(&spec)?).await?;
+        Ok(())
+    }
+
+    fn with_urls(&self, spec: &str) -> Result<String> {
+        let mut doc: toml_edit::DocumentMut = spec.parse()?;
+        doc["fic"]["url"] = toml_edit::value(&self.fic.url);
+        for (key, chapter) in &self.chapters {
+            if !chapter.url.is_empty() {
+                doc["chapters"][key]["url"] = toml_edit::value(&chapter.url);
+            }
+        }
+        Ok(doc.to_string())
+    }
+
+    /// The chapter posted together with the work itself: the first entry of
+    /// `chapters`, or the work details when there are no chapters.
+    pub fn first_chapter(&self) -> &FicDetails {
+        self.chapters.values().next().unwrap_or(&self.fic)
+    }
+
+    /// AO3 only asks for chapter details on works of more than one chapter.
+    pub fn is_chaptered(&self) -> bool {
+        self.meta.total_chapters > 1 || self.chapters.len() > 1
+    }
+
+    /// The text file of the chapter at `index`; the first chapter falls back
+    /// on the work's own.
+    pub fn chapter_file(&self, index: usize) -> Option<&PathBuf> {
+        let chapter = self.chapters.values().nth(index)?;
+        match index {
+            0 => chapter.file.as_ref().or(self.fic.file.as_ref()),
+            _ => chapter.file.as_ref(),
+        }
+    }
+
+    /// The first chapter after the one posted with the work that has no URL
+    /// recorded yet.
+    pub fn next_unposted(&self) -> Option<(&String, &FicDetails)> {
+        self.chapters.iter().skip(1).find(|(_, c)| c.url.is_empty())
+    }
+
+    /// Paths in the spec are relative to the directory the spec lives in.
+    pub fn resolve_file(&self, file: &Path) -> PathBuf {
+        match self.path.parent() {
+            Some(dir) => dir.join(file),
+            None => file.to_path_buf(),
+        }
+    }
+
+    /// The text file of the first chapter, falling back on the work's own.
+    pub fn work_file(&self) -> Option<&PathBuf> {
+        self.first_chapter()
+            .file
+            .as_ref()
+            .or(self.fic.file.as_ref())
+    }
+
+    pub async fn render_file(&self, file: &Path) -> Result<String> {
+        let file = self.resolve_file(file);
+        let text = match tokio::fs::read_to_string(&file).await {
+            Ok(ok) => ok,
+            Err(e) => bail!("Failed to read {}: {}", file.to_string_lossy(), e),
+        };
+        self.meta.format.to_html(&text).await
     }
 }
 
@@ -61,8 +148,13 @@ impl Default for FicTags {
 pub struct FicDetails {
     #[serde(default)]
     pub url: String,
-    #[serde(default)]
-    pub author_pseud: Option<String>,
+    /// One pseud, or a list of them to post under several at once.
+    #[serde(
+        default,
+        with = "one_or_many",
+        skip_serializing_if = "IndexSet::is_empty"
+    )]
+    pub author_pseud: IndexSet<String>,
     #[serde(default)]
     pub co_authors: IndexSet<String>,
     #[serde(default)]
@@ -93,6 +185,30 @@ pub struct FicMeta {
     pub in_series: Option<String>,
     #[serde(default)]
     pub publication_date: Option<String>,
+    /// The ficwright profile the work must be posted from.
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+impl FicMeta {
+    /// Parses a `YYYY-MM-DD` date into year, month and day.
+    pub fn parse_date(date: &str) -> Option<(u32, u32, u32)> {
+        let re = regex::Regex::new(r"^(\d{4})-(\d{2})-(\d{2})$").unwrap();
+        let cap = re.captures(date.trim())?;
+        let year: u32 = cap[1].parse().ok()?;
+        let month: u32 = cap[2].parse().ok()?;
+        let day: u32 = cap[3].parse().ok()?;
+        let leap =
+            year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+        let days = match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if leap => 29,
+            2 => 28,
+            _ => return None,
+        };
+        (1..=days).contains(&day).then_some((year, month, day))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Default, Deserialize)]
@@ -104,38 +220,272 @@ pub struct FicRemix {
     pub translated: bool,
 }
 
+/// Expands a leading `~/` to the user's home directory.
+pub fn expand_home(file: &Path) -> Result<PathBuf> {
+    Ok(if let Ok(f) = file.strip_prefix("~/") {
+        let home = PathBuf::from(std::env::var("HOME")?);
+        home.join(f)
+    } else {
+        file.to_path_buf()
+    })
+}
+
+/// Settings for ficwright itself, as opposed to any one fic.
+#[derive(Debug, Clone, Serialize, Default, Deserialize)]
+pub struct FicwrightConfig {
+    /// Attach to this WebDriver server instead of starting one.
+    #[serde(default)]
+    pub webdriver_url: Option<String>,
+
+    #[serde(default)]
+    pub waits: WaitConfig,
+
+    /// An otwarchive instance to use instead of AO3.
+    #[serde(default)]
+    pub archive_url: Option<String>,
+
+    /// Encrypts cookie files when set.
+    #[serde(default)]
+    pub cookie_key: Option<CookieKey>,
+
+    /// Used when neither `--profile` nor the spec names one.
+    #[serde(default)]
+    pub default_profile: Option<String>,
+
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// An AO3 account to post from.
+#[derive(Debug, Clone, Serialize, Default, Deserialize)]
+pub struct Profile {
+    /// Where the account's login cookies are kept.
+    pub cookies: PathBuf,
+    /// The otwarchive instance the account is on, if not the configured one.
+    #[serde(default)]
+    pub archive_url: Option<String>,
+    /// The pseud to post as when the spec names none.
+    #[serde(default)]
+    pub pseud: Option<String>,
+    /// Logs in without a person; see `Credentials` for what it must print.
+    #[serde(default)]
+    pub credential_command: Option<String>,
+    /// The user name or email to log in with, if the command prints none.
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+/// How long to wait on the driver and the site before giving up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WaitConfig {
+    /// In seconds, for elements to appear and pages to load.
+    pub timeout: f64,
+    /// In milliseconds, between checks.
+    pub interval: u64,
+    /// In seconds, for the WebDriver server to report that it is ready.
+    pub startup: f64,
+}
+
+impl Default for WaitConfig {
+    fn default() -> Self {
+        Self {
+            timeout: 60.0,
+            interval: 250,
+            startup: 20.0,
+        }
+    }
+}
+
+impl WaitConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.timeout)
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval)
+    }
+
+    pub fn startup(&self) -> Duration {
+        Duration::from_secs_f64(self.startup)
+    }
+}
+
+impl FicwrightConfig {
+    /// A missing config file is an empty one.
+    pub async fn read_from_file(file: &Path) -> Result<Self> {
+        let file = expand_home(file)?;
+        let contents = match tokio::fs::read_to_string(&file).await {
+            Ok(ok) => ok,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => bail!("Failed to read {}: {}", file.to_string_lossy(), e),
+        };
+        match toml::from_str(&contents) {
+            Ok(config) => Ok(config),
+            Err(e) => bail!("Failed to read {}: {}", file.to_string_lossy(), e.message()),
+        }
+    }
+}
+
+/// AO3's remember-me cookie, which outlives the browser session.
+pub const REMEMBER_ME: &str = "remember_user_token";
+
+/// Warn about the remember-me cookie this long before it expires.
+pub const EXPIRY_WARNING: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CookieConfig {
-    pub cookies: HashMap<String, String>,
+    #[serde(deserialize_with = "cookie_records")]
+    pub cookies: Vec<CookieRecord>,
 }
 
-impl CookieConfig {
-    pub async fn save_to_file(&self, file: &Path) -> Result<()> {
-        let file = if let Ok(f) = file.strip_prefix("~/") {
-            let home = PathBuf::from(std::env::var("HOME")?);
-            home.join(f)
-        } else {
-            file.to_path_buf()
+/// A cookie with every attribute WebDriver reports, which is more than
+/// thirtyfour's `Cookie` keeps.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CookieRecord {
+    pub name: String,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secure: Option<bool>,
+    #[serde(default, alias = "httpOnly", skip_serializing_if = "Option::is_none")]
+    pub http_only: Option<bool>,
+    #[serde(default, alias = "sameSite", skip_serializing_if = "Option::is_none")]
+    pub same_site: Option<String>,
+    /// Seconds since the Unix epoch; session cookies have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<i64>,
+}
+
+impl CookieRecord {
+    /// How long until the cookie expires, or `Err` with how long ago it did.
+    pub fn expires_in(&self) -> Option<std::result::Result<Duration, Duration>> {
+        let expiry = UNIX_EPOCH + Duration::from_secs(self.expiry?.max(0) as u64);
+        Some(match expiry.duration_since(SystemTime::now()) {
+            Ok(left) => Ok(left),
+            Err(e) => Err(e.duration()),
+        })
+    }
+
+    /// Whether the cookie was set by `host`, or by a domain above it.
+    pub fn belongs_to(&self, host: &str) -> bool {
+        let Some(domain) = &self.domain else {
+            return true;
         };
-        tokio::fs::write(file, toml::to_string_pretty(&self)?).await?;
-        Ok(())
+        let domain = domain.trim_start_matches('.');
+        host == domain || host.ends_with(&format!(".{domain}"))
     }
 
-    pub async fn read_from_file(file: &Path) -> Result<Self> {
+    /// The cookie as the WebDriver add cookie command takes it.
+    pub fn to_webdriver(&self) -> serde_json::Value {
+        let mut cookie = serde_json::json!({ "name": self.name, "value": self.value });
+        for (key, value) in [
+            ("domain", self.domain.clone().map(Into::into)),
+            ("path", self.path.clone().map(Into::into)),
+            ("secure", self.secure.map(Into::into)),
+            ("httpOnly", self.http_only.map(Into::into)),
+            ("sameSite", self.same_site.clone().map(Into::into)),
+            ("expiry", self.expiry.map(Into::into)),
+        ] {
+            if let Some(value) = value {
+                cookie[key] = value;
+            }
+        }
+        cookie
+    }
+}
+
+/// Reads the cookie list, or the name to value map older versions wrote.
+fn cookie_records<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<CookieRecord>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Records(Vec<CookieRecord>),
+        Legacy(HashMap<String, String>),
+    }
+
+    Ok(match Stored::deserialize(deserializer)? {
+        Stored::Records(records) => records,
+        Stored::Legacy(map) => map
+            .into_iter()
+            .map(|(name, value)| CookieRecord {
+                name,
+                value,
+                ..Default::default()
+            })
+            .collect(),
+    })
+}
+
+/// A set written as a bare string when it holds one item.
+mod one_or_many {
+    use indexmap::IndexSet;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(set: &IndexSet<String>, s: S) -> Result<S::Ok, S::Error> {
+        match set.len() {
+            1 => s.serialize_str(&set[0]),
+            _ => set.serialize(s),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<IndexSet<String>, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum OneOrMany {
+            One(String),
+            Many(IndexSet<String>),
+        }
+
+        Ok(match OneOrMany::deserialize(d)? {
+            OneOrMany::One(one) => IndexSet::from([one]),
+            OneOrMany::Many(many) => many,
+        })
+    }
+}
+
+fn days(d: Duration) -> u64 {
+    d.as_secs() / (24 * 60 * 60)
+}
+
+impl CookieConfig {
+    /// Saves the cookies readable only by their owner, and encrypted if a
+    /// key is given.
+    pub async fn save_to_file(&self, file: &Path, key: Option<&Secret>) -> Result<()> {
+        let file = expand_home(file)?;
+        let mut contents = toml::to_string_pretty(&self)?;
+        if let Some(key) = key {
+            contents = key.encrypt(&contents)?;
+        }
+        write_private(&file, &contents).await
+    }
+
+    /// Reads the cookies, refusing files that others can read. A plain file
+    /// is read even with a key, to be encrypted when next saved.
+    pub async fn read_from_file(file: &Path, key: Option<&Secret>) -> Result<Self> {
         println_async!("Reading cookie file: {}", file.to_string_lossy());
-        let file = if let Ok(f) = file.strip_prefix("~/") {
-            let home = PathBuf::from(std::env::var("HOME")?);
-            home.join(f)
-        } else {
-            file.to_path_buf()
-        };
-        let contents = match tokio::fs::read_to_string(&file).await {
+        let file = expand_home(file)?;
+        let mut contents = match read_private(&file).await {
             Ok(ok) => ok,
             Err(e) => {
-                println_async!("Fauled to read cookie file: {}", e.kind());
-                bail!(e)
+                println_async!("Failed to read cookie file");
+                return Err(e);
             }
         };
+        if is_encrypted(&contents) {
+            let Some(key) = key else {
+                bail!(
+                    "{} is encrypted, but no cookie_key is configured",
+                    file.to_string_lossy()
+                );
+            };
+            contents = key.decrypt(&contents)?;
+        }
         Ok(match toml::from_str::<Self>(&contents) {
             Ok(cookies) => cookies,
             Err(e) => {
@@ -145,15 +495,96 @@ impl CookieConfig {
         })
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = Cookie> {
-        self.cookies
-            .iter()
-            .map(|c| Cookie::new(c.0.clone(), c.1.clone()))
+    /// The cookies that have not expired yet. Fails if the remember-me
+    /// cookie has, since the session cannot be restored without it, and
+    /// warns when it is about to.
+    pub async fn usable(&self) -> Result<Vec<&CookieRecord>> {
+        let mut usable = vec![];
+        for cookie in &self.cookies {
+            match cookie.expires_in() {
+                Some(Err(ago)) if cookie.name == REMEMBER_ME => {
+                    return Err(Failure::NotLoggedIn.report(format!(
+                        "The saved AO3 session expired {} day(s) ago, run `ficwright ao3 login` again",
+                        days(ago)
+                    )));
+                }
+                Some(Err(_)) => continue,
+                Some(Ok(left)) if cookie.name == REMEMBER_ME && left < EXPIRY_WARNING => {
+                    eprintln_async!(
+                        "Warning: the saved AO3 session expires in {} day(s)",
+                        days(left)
+                    );
+                }
+                _ => {}
+            }
+            usable.push(cookie);
+        }
+        Ok(usable)
     }
 
-    pub fn new(cookies: Vec<Cookie>) -> Self {
-        let cookies = cookies.into_iter().map(|c| (c.name, c.value)).collect();
-
+    pub fn new(cookies: Vec<CookieRecord>) -> Self {
         CookieConfig { cookies }
     }
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_cookie_records() {
+    let legacy: CookieConfig =
+        toml::from_str("[cookies]\n_otwarchive_session = \"abc\"\n").unwrap();
+    assert_eq!(legacy.cookies[0].name, "_otwarchive_session");
+    assert_eq!(legacy.usable().await.unwrap().len(), 1);
+
+    let expired: CookieConfig = toml::from_str(
+        "[[cookies]]\nname = \"remember_user_token\"\nvalue = \"x\"\nexpiry = 1\nhttp_only = true\n",
+    )
+    .unwrap();
+    assert_eq!(expired.cookies[0].to_webdriver()["httpOnly"], true);
+    assert!(expired.cookies[0].belongs_to("localhost"));
+
+    let scoped = CookieRecord {
+        domain: Some(".archiveofourown.org".to_string()),
+        ..Default::default()
+    };
+    assert!(scoped.belongs_to("archiveofourown.org"));
+    assert!(scoped.belongs_to("www.archiveofourown.org"));
+    assert!(!scoped.belongs_to("localhost"));
+    assert!(expired.usable().await.is_err());
+}
+
+#[test]
+fn test_author_pseud() {
+    let one: FicDetails = toml::from_str("author_pseud = \"Main\"").unwrap();
+    let many: FicDetails = toml::from_str("author_pseud = [\"Main\", \"Alt\"]").unwrap();
+    assert_eq!(one.author_pseud, IndexSet::from(["Main".to_string()]));
+    assert_eq!(many.author_pseud.len(), 2);
+    assert!(
+        toml::to_string(&one)
+            .unwrap()
+            .contains("author_pseud = \"Main\"")
+    );
+}
+
+#[test]
+fn test_save_urls() {
+    let spec = "# My fic\n[fic]\ntitle = \"Title\"  # working title\n\n[chapters.01]\ntitle = \"One\"\n\n[chapters.02]\ntitle = \"Two\"\n";
+    let mut fic = Fanfiction::default();
+    fic.fic.url = "https://archiveofourown.org/works/1".to_string();
+    fic.chapters.insert(
+        "01".to_string(),
+        FicDetails {
+            url: "https://archiveofourown.org/works/1/chapters/10".to_string(),
+            ..Default::default()
+        },
+    );
+    fic.chapters.insert("02".to_string(), FicDetails::default());
+
+    let saved = fic.with_urls(spec).unwrap();
+    assert!(saved.starts_with("# My fic\n[fic]\ntitle = \"Title\"  # working title\n"));
+    let doc: toml_edit::DocumentMut = saved.parse().unwrap();
+    assert_eq!(doc["fic"]["url"].as_str(), Some(fic.fic.url.as_str()));
+    assert_eq!(
+        doc["chapters"]["01"]["url"].as_str(),
+        Some(fic.chapters["01"].url.as_str())
+    );
+    assert!(doc["chapters"]["02"].get("url").is_none());
+}
@@ -1,25 +1,352 @@
-use thirtyfour::WebDriver;
+use std::{
+    sync::{Arc, OnceLock},
+    time::Instant,
+};
 
-use crate::{Result, config::CookieConfig};
+use clap::ValueEnum;
+use regex::Regex;
+use rootcause::{bail, report};
+use thirtyfour::{
+    By, Capabilities, ChromiumLikeCapabilities, DesiredCapabilities, WebDriver,
+    common::{
+        command::{Command, FormatRequestData},
+        config::WebDriverConfig,
+        requestdata::RequestData,
+        types::SessionId,
+    },
+    extensions::query::ElementPollerWithTimeout,
+    session::handle::SessionHandle,
+};
+
+use crate::{
+    Result,
+    config::{CookieRecord, WaitConfig},
+    forms::rejection::Rejection,
+    render::unescape_html,
+};
+
+/// The archive used unless the config or profile names another.
+pub const AO3: &str = "https://archiveofourown.org";
+
+static ARCHIVE: OnceLock<String> = OnceLock::new();
+
+/// Points the rest of the run at an otwarchive instance other than AO3.
+pub fn set_archive(url: &str) -> Result<()> {
+    let parsed = reqwest::Url::parse(url)?;
+    if parsed.host_str().is_none() {
+        bail!("Archive URL {:?} has no host", url);
+    }
+    let _ = ARCHIVE.set(url.trim_end_matches('/').to_string());
+    Ok(())
+}
+
+/// The base URL of the archive in use, without a trailing slash.
+pub fn archive() -> &'static str {
+    ARCHIVE.get().map_or(AO3, String::as_str)
+}
+
+/// The host of the archive in use, which saved cookies must belong to.
+pub fn archive_host() -> String {
+    reqwest::Url::parse(archive())
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Browser {
+    #[default]
+    Firefox,
+    Chrome,
+}
+
+impl Browser {
+    /// The WebDriver server that drives this browser.
+    pub fn driver_binary(self) -> &'static str {
+        match self {
+            Self::Firefox => "geckodriver",
+            Self::Chrome => "chromedriver",
+        }
+    }
+
+    pub fn capabilities(self, headless: bool) -> Result<Capabilities> {
+        Ok(match self {
+            Self::Firefox => {
+                let mut caps = DesiredCapabilities::firefox();
+                if headless {
+                    caps.set_headless()?;
+                } else {
+                    caps.unset_headless()?;
+                }
+                caps.into()
+            }
+            Self::Chrome => {
+                let mut caps = DesiredCapabilities::chrome();
+                if headless {
+                    // Containers rarely give Chrome a sandbox or a usable /dev/shm.
+                    caps.set_headless()?;
+                    caps.set_no_sandbox()?;
+                    caps.set_disable_dev_shm_usage()?;
+                }
+                caps.into()
+            }
+        })
+    }
+}
 
 pub trait DriverExts {
-    fn add_cookies(&self, conf: &CookieConfig) -> impl Future<Output = Result<()>>;
+    fn add_cookies(&self, cookies: &[&CookieRecord]) -> impl Future<Output = Result<()>>;
+
+    fn get_cookie_records(&self) -> impl Future<Output = Result<Vec<CookieRecord>>>;
 
     fn ao3(&self, path: impl AsRef<str>) -> impl Future<Output = Result<()>>;
+
+    fn wait_for_saved(&self, pattern: &Regex, scope: &str) -> impl Future<Output = Result<String>>;
+
+    fn chapter_paths(&self, work: u64) -> impl Future<Output = Result<Vec<String>>>;
+
+    fn logged_in_user(&self) -> impl Future<Output = Result<Option<String>>>;
+
+    fn pseuds(&self, user: &str) -> impl Future<Output = Result<Vec<String>>>;
 }
 
 impl DriverExts for WebDriver {
-    async fn add_cookies(&self, conf: &CookieConfig) -> Result<()> {
-        for cookie in conf.iter() {
-            self.add_cookie(cookie).await?;
+    async fn add_cookies(&self, cookies: &[&CookieRecord]) -> Result<()> {
+        let host = archive_host();
+        for cookie in cookies.iter().filter(|c| c.belongs_to(&host)) {
+            self.handle
+                .cmd(AddCookieRecord(cookie.to_webdriver()))
+                .await?;
         }
         self.refresh().await?;
         Ok(())
     }
 
+    /// Every cookie of the current page, with all the attributes the
+    /// WebDriver server reports.
+    async fn get_cookie_records(&self) -> Result<Vec<CookieRecord>> {
+        Ok(self.handle.cmd(Command::GetAllCookies).await?.value()?)
+    }
+
     async fn ao3(&self, path: impl AsRef<str>) -> Result<()> {
-        Ok(self
-            .goto(format!("https://archiveofourown.org{}", path.as_ref()))
-            .await?)
+        Ok(self.goto(format!("{}{}", archive(), path.as_ref())).await?)
+    }
+
+    /// Waits for the page a submitted form leads to. AO3 shows a refused
+    /// form again with a `div#error` list, sometimes at the very same path,
+    /// so that list is checked first. `scope` is as for `FormError::new`.
+    async fn wait_for_saved(&self, pattern: &Regex, scope: &str) -> Result<String> {
+        let mut path = String::new();
+        let mut errors = vec![];
+        let found = poll_until(&self.handle, async || {
+            errors = self.find_all(By::Css("div#error")).await?;
+            path = self.current_url().await?.path().to_string();
+            Ok(!errors.is_empty() || pattern.is_match(&path))
+        })
+        .await?;
+        if let Some(list) = errors.first() {
+            return Err(Rejection::read(list, scope).await?.report());
+        }
+        if !found {
+            bail!("Timed out waiting for a page matching {}", pattern);
+        }
+        Ok(path)
     }
+
+    /// The paths of a work's chapters, in order, from its chapter index.
+    async fn chapter_paths(&self, work: u64) -> Result<Vec<String>> {
+        self.ao3(format!("/works/{work}/navigate")).await?;
+        let mut paths = vec![];
+        for link in self.find_all(By::Css("ol.chapter.index li a")).await? {
+            if let Some(href) = link.attr("href").await? {
+                paths.push(href);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Who the browser is logged in as. AO3 greets logged in users in the
+    /// header of every page, and offers everyone else a login dropdown.
+    async fn logged_in_user(&self) -> Result<Option<String>> {
+        let greeting = self
+            .find_all(By::Css("#greeting a[href*=\"/users/\"]"))
+            .await?;
+        if let Some(link) = greeting.first() {
+            let href = link.attr("href").await?.unwrap_or_default();
+            let re = Regex::new(r"/users/([^/?#]+)").unwrap();
+            if let Some(cap) = re.captures(&href) {
+                return Ok(Some(cap[1].to_string()));
+            }
+        }
+        if !self.find_all(By::Id("login-dropdown")).await?.is_empty() {
+            return Ok(None);
+        }
+        bail!(
+            "Cannot tell whether {} is logged in",
+            self.current_url().await?
+        )
+    }
+
+    async fn pseuds(&self, user: &str) -> Result<Vec<String>> {
+        self.ao3(format!("/users/{user}/pseuds")).await?;
+        let mut pseuds = vec![];
+        for link in self
+            .find_all(By::Css("ul.pseud.index li .heading a"))
+            .await?
+        {
+            pseuds.push(link.text().await?);
+        }
+        Ok(pseuds)
+    }
+}
+
+/// Adds a cookie as given, since thirtyfour's `Cookie` drops `httpOnly`.
+#[derive(Debug)]
+struct AddCookieRecord(serde_json::Value);
+
+impl FormatRequestData for AddCookieRecord {
+    fn format_request(&self, session_id: &SessionId) -> RequestData {
+        RequestData::new(
+            reqwest::Method::POST,
+            format!("session/{session_id}/cookie"),
+        )
+        .add_body(serde_json::json!({ "cookie": self.0 }))
+    }
+}
+
+/// Checks `done` until it holds or the session's poller gives up, which
+/// happens after the configured wait timeout.
+pub async fn poll_until(
+    handle: &SessionHandle,
+    mut done: impl AsyncFnMut() -> Result<bool>,
+) -> Result<bool> {
+    let mut poller = handle.config().poller.start();
+    loop {
+        if done().await? {
+            return Ok(true);
+        }
+        if !poller.tick().await {
+            return Ok(false);
+        }
+    }
+}
+
+/// The session config that makes element lookups and `poll_until` follow
+/// the configured waits.
+pub fn webdriver_config(waits: &WaitConfig) -> Result<WebDriverConfig> {
+    let poller = ElementPollerWithTimeout::new(waits.timeout(), waits.interval());
+    Ok(WebDriverConfig::builder()
+        .poller(Arc::new(poller))
+        .build()?)
+}
+
+/// Waits for the WebDriver server at `url` to report itself ready on its
+/// `/status` endpoint.
+pub async fn wait_for_driver(url: &str, waits: &WaitConfig) -> Result<()> {
+    let status = format!("{}/status", url.trim_end_matches('/'));
+    let start = Instant::now();
+    loop {
+        if let Ok(res) = reqwest::get(&status).await
+            && let Ok(body) = res.json::<serde_json::Value>().await
+            && body["value"]["ready"].as_bool() == Some(true)
+        {
+            return Ok(());
+        }
+        if start.elapsed() >= waits.startup() {
+            bail!("The WebDriver server at {} did not become ready", url);
+        }
+        tokio::time::sleep(waits.interval()).await;
+    }
+}
+
+/// What AO3 makes of a tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagStatus {
+    /// Filterable, and suggested by the tag fields.
+    Canonical,
+    /// Shown as the canonical tag named here.
+    Synonym(String),
+    /// Kept as typed, whether new or not yet wrangled.
+    Unwrangled,
+}
+
+/// Looks a tag up on its public page on the archive.
+pub async fn tag_status(tag: &str) -> Result<TagStatus> {
+    let mut url = reqwest::Url::parse(archive())?;
+    url.path_segments_mut()
+        .map_err(|()| report!("{} cannot have tag pages", archive()))?
+        .pop_if_empty()
+        .extend(["tags", &tag_path(tag)]);
+
+    let res = reqwest::get(url).await?;
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(TagStatus::Unwrangled);
+    }
+    Ok(parse_tag_page(&res.error_for_status()?.text().await?))
+}
+
+/// AO3 spells out the characters in tag names that would upset URLs.
+fn tag_path(tag: &str) -> String {
+    tag.replace('*', "*x*")
+        .replace('/', "*s*")
+        .replace('&', "*a*")
+        .replace('.', "*d*")
+        .replace('?', "*q*")
+        .replace('#', "*h*")
+}
+
+fn parse_tag_page(page: &str) -> TagStatus {
+    let synonym = Regex::new(r"has been made a synonym of\s*<a[^>]*>([^<]*)</a>").unwrap();
+    if let Some(cap) = synonym.captures(page) {
+        return TagStatus::Synonym(unescape_html(cap[1].trim()));
+    }
+    let common = Regex::new(r"It(?:'|&#39;|’)s a common tag").unwrap();
+    if common.is_match(page) {
+        TagStatus::Canonical
+    } else {
+        TagStatus::Unwrangled
+    }
+}
+
+/// Extracts the work ID from a path like `/works/12345/preview`.
+pub fn work_id(path: &str) -> Option<u64> {
+    let re = Regex::new(r"^/works/(\d+)").unwrap();
+    re.captures(path)?.get(1)?.as_str().parse().ok()
+}
+
+/// Extracts the work ID from a work URL, or takes a bare ID as is.
+pub fn work_id_of(url: &str) -> Option<u64> {
+    let re = Regex::new(r"^(?:(\d+)|.*/works/(\d+).*)$").unwrap();
+    let cap = re.captures(url.trim())?;
+    cap.get(1).or(cap.get(2))?.as_str().parse().ok()
+}
+
+/// Extracts the chapter ID from a chapter URL.
+pub fn chapter_id_of(url: &str) -> Option<u64> {
+    let re = Regex::new(r"/chapters/(\d+)").unwrap();
+    re.captures(url)?.get(1)?.as_str().parse().ok()
+}
+
+#[test]
+fn test_tag_status() {
+    assert_eq!(
+        tag_path("Fluff & Angst/Hurt.Comfort"),
+        "Fluff *a* Angst*s*Hurt*d*Comfort"
+    );
+    assert_eq!(
+        parse_tag_page(
+            "<p>&quot;HP&quot; has been made a synonym of <a class=\"tag\" href=\"/tags/x\">Harry Potter - J. K. Rowling</a>.</p>"
+        ),
+        TagStatus::Synonym("Harry Potter - J. K. Rowling".to_string())
+    );
+    assert_eq!(
+        parse_tag_page("<p>This tag belongs to the Fandom Category. It&#39;s a common tag.</p>"),
+        TagStatus::Canonical
+    );
+    assert_eq!(
+        parse_tag_page(
+            "<p>This tag has not been marked common and can't be filtered on (yet).</p>"
+        ),
+        TagStatus::Unwrangled
+    );
 }
@@ -0,0 +1,75 @@
+//! Exit codes for scripts calling ficwright.
+//!
+//! | code | meaning                                             |
+//! |------|-----------------------------------------------------|
+//! | 0    | success                                             |
+//! | 1    | any other error                                     |
+//! | 2    | bad command line                                    |
+//! | 3    | the fic spec or its text files have problems        |
+//! | 4    | not logged in to AO3, or the session expired        |
+//! | 5    | a person is needed, but `--non-interactive` is set  |
+//! | 6    | AO3 refused the submitted form                      |
+//! | 7    | the browser or WebDriver server failed              |
+
+use std::fmt;
+
+use rootcause::{Report, report};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    InvalidSpec,
+    NotLoggedIn,
+    NeedsInteraction,
+    Rejected,
+    Driver,
+}
+
+impl Failure {
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::InvalidSpec => 3,
+            Self::NotLoggedIn => 4,
+            Self::NeedsInteraction => 5,
+            Self::Rejected => 6,
+            Self::Driver => 7,
+        }
+    }
+
+    /// A report of this kind of failure, explained by `message`.
+    pub fn report(self, message: impl fmt::Display) -> Report {
+        report!("{}", message).context(self).into_dyn_any()
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::InvalidSpec => "Invalid fic spec",
+            Self::NotLoggedIn => "Not logged in",
+            Self::NeedsInteraction => "Needs interaction",
+            Self::Rejected => "Rejected by AO3",
+            Self::Driver => "WebDriver failure",
+        })
+    }
+}
+
+/// The exit code for a failed run: that of the outermost `Failure` in the
+/// report, or 1 when there is none.
+pub fn exit_code(report: &Report) -> u8 {
+    report
+        .iter_reports()
+        .find_map(|r| r.downcast_current_context::<Failure>().copied())
+        .map_or(1, Failure::exit_code)
+}
+
+#[test]
+fn test_exit_code() {
+    use rootcause::prelude::ResultExt;
+
+    let plain = report!("Something broke");
+    assert_eq!(exit_code(&plain), 1);
+
+    let nested: Result<(), Report> = Err(Failure::NotLoggedIn.report("Session expired"));
+    let nested = nested.context("While posting").unwrap_err().into_dyn_any();
+    assert_eq!(exit_code(&nested), 4);
+}
@@ -1,10 +1,11 @@
+use rootcause::bail;
 use thirtyfour::components::{Component, ElementResolver};
 
 use thirtyfour::{By, WebElement};
 
 use crate::Result;
-use crate::config::{FicDetails, FicMeta};
-use crate::forms::{Autocomplete, AutocompleteEntry, Checkbox, DropdownSelector};
+use crate::config::{Fanfiction, FicMeta, FicRemix};
+use crate::forms::{Autocomplete, AutocompleteEntry, Checkbox, DropdownSelector, TextField};
 
 #[derive(Debug, Clone, Component)]
 pub struct AssociationsForm {
@@ -28,7 +29,7 @@ pub struct AssociationsForm {
 
     #[by(css = "dt.chaptered.wip > input[type=\"checkbox\"]")]
     is_chaptered: ElementResolver<Checkbox>,
-    #[by(css = "dd..chaptered.wip")]
+    #[by(css = "dd.chaptered.wip")]
     chaptered: ElementResolver<ChapteredForm>,
 
     #[by(css = "dt.backdate > input[type=\"checkbox\"]")]
@@ -44,7 +45,154 @@ pub struct AssociationsForm {
 }
 
 impl AssociationsForm {
-    pub async fn set_all(&self, fic: &FicDetails, meta: &FicMeta) -> Result<()> {
+    pub async fn set_all(&self, fic: &Fanfiction) -> Result<()> {
+        let meta = &fic.meta;
+
+        self.collections
+            .resolve()
+            .await?
+            .set(&meta.challenges)
+            .await?;
+        self.gift_to.resolve().await?.set(&meta.gift_to).await?;
+
+        self.set_remix(fic.remix.as_ref()).await?;
+        self.set_series(meta.in_series.as_deref()).await?;
+        self.set_chapters(fic).await?;
+        self.set_publication_date(meta.publication_date.as_deref())
+            .await?;
+        self.set_language(&meta.language).await?;
+        self.set_skin(meta.work_skin.as_deref()).await?;
+
+        Ok(())
+    }
+
+    /// Reads the associations back. The text format is not on the form, so
+    /// it is left at its default.
+    pub async fn get_meta(&self) -> Result<FicMeta> {
+        let collections = self.collections.resolve().await?.list_entries().await?;
+        let gift_to = self.gift_to.resolve().await?.list_entries().await?;
+
+        let language = self.language.resolve().await?.current_innerhtml().await?;
+
+        Ok(FicMeta {
+            language: language.unwrap_or_default(),
+            challenges: collections.into_iter().collect(),
+            gift_to: gift_to.into_iter().collect(),
+            work_skin: self.get_skin().await?,
+            total_chapters: self.get_total_chapters().await?,
+            in_series: self.get_series().await?,
+            publication_date: self.get_publication_date().await?,
+            ..Default::default()
+        })
+    }
+
+    pub async fn get_remix(&self) -> Result<Option<FicRemix>> {
+        if !self.is_remixed.resolve().await?.state().await? {
+            return Ok(None);
+        }
+        Ok(Some(self.remix.resolve().await?.get().await?))
+    }
+
+    pub async fn get_series(&self) -> Result<Option<String>> {
+        if !self.is_serial.resolve().await?.state().await? {
+            return Ok(None);
+        }
+        self.serial.resolve().await?.get().await
+    }
+
+    /// An unchaptered work has the one chapter; zero means an unknown total.
+    pub async fn get_total_chapters(&self) -> Result<u64> {
+        if !self.is_chaptered.resolve().await?.state().await? {
+            return Ok(1);
+        }
+        self.chaptered.resolve().await?.get_total().await
+    }
+
+    pub async fn get_publication_date(&self) -> Result<Option<String>> {
+        if !self.is_backdated.resolve().await?.state().await? {
+            return Ok(None);
+        }
+        self.backdating.resolve().await?.get().await
+    }
+
+    pub async fn get_skin(&self) -> Result<Option<String>> {
+        let select = self.skin.resolve().await?;
+        match select.current_value().await? {
+            Some(value) if !value.is_empty() => select.current_innerhtml().await,
+            _ => Ok(None),
+        }
+    }
+
+    pub async fn set_remix(&self, remix: Option<&FicRemix>) -> Result<()> {
+        let check = self.is_remixed.resolve().await?;
+        check.set(remix.is_some()).await?;
+        if let Some(remix) = remix {
+            self.remix.resolve().await?.set(remix).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn set_series(&self, series: Option<&str>) -> Result<()> {
+        let check = self.is_serial.resolve().await?;
+        check.set(series.is_some()).await?;
+        if let Some(series) = series {
+            self.serial.resolve().await?.set(series).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn set_chapters(&self, fic: &Fanfiction) -> Result<()> {
+        let chaptered = fic.is_chaptered();
+        self.is_chaptered.resolve().await?.set(chaptered).await?;
+        if chaptered {
+            let title = fic.chapters.values().next().map(|c| c.title.as_str());
+            self.chaptered
+                .resolve()
+                .await?
+                .set(title.unwrap_or_default(), fic.meta.total_chapters)
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn set_publication_date(&self, date: Option<&str>) -> Result<()> {
+        let check = self.is_backdated.resolve().await?;
+        check.set(date.is_some()).await?;
+        if let Some(date) = date {
+            let Some((year, month, day)) = FicMeta::parse_date(date) else {
+                bail!("Malformed publication date {:?}, expected YYYY-MM-DD", date);
+            };
+            self.backdating
+                .resolve()
+                .await?
+                .set(year, month, day)
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn set_language(&self, language: &str) -> Result<()> {
+        if !self
+            .language
+            .resolve()
+            .await?
+            .select_by_innerhtml(language)
+            .await?
+        {
+            bail!("AO3 does not offer the language {:?}", language);
+        }
+        Ok(())
+    }
+
+    pub async fn set_skin(&self, skin: Option<&str>) -> Result<()> {
+        let select = self.skin.resolve().await?;
+        let found = match skin {
+            Some(skin) => select.select_by_innerhtml(skin).await?,
+            None => select.select_by_value(&String::new()).await?,
+        };
+        if !found {
+            bail!("No work skin named {:?}", skin.unwrap_or_default());
+        }
         Ok(())
     }
 }
@@ -52,19 +200,213 @@ impl AssociationsForm {
 #[derive(Debug, Clone, Component)]
 pub struct ChapteredForm {
     base: WebElement,
+
+    #[by(css = "input#work_chapter_attributes_title")]
+    title: ElementResolver<TextField>,
+
+    #[by(css = "input#work_wip_length")]
+    total: ElementResolver<TextField>,
+}
+
+impl ChapteredForm {
+    pub async fn get_total(&self) -> Result<u64> {
+        let total = self.total.resolve().await?.value().await?;
+        Ok(total.trim().parse().unwrap_or_default())
+    }
+
+    /// A total of zero chapters is an unknown total, which AO3 writes as "?".
+    /// The chapter title is only asked for when posting a new work.
+    pub async fn set(&self, title: &str, total: u64) -> Result<()> {
+        let has_title = !self
+            .base
+            .find_all(By::Css("input#work_chapter_attributes_title"))
+            .await?
+            .is_empty();
+        if has_title {
+            let tit = self.title.resolve().await?;
+            tit.delete_all().await?;
+            tit.push_text(title, None).await?;
+        }
+
+        let total = if total == 0 {
+            "?".to_string()
+        } else {
+            total.to_string()
+        };
+        let tot = self.total.resolve().await?;
+        tot.delete_all().await?;
+        tot.push_text(&total, None).await?;
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Component)]
 pub struct SerialForm {
     base: WebElement,
+
+    #[by(css = "select#work_series_attributes_id")]
+    existing: ElementResolver<DropdownSelector>,
+
+    #[by(css = "input#work_series_attributes_title")]
+    new_title: ElementResolver<TextField>,
+}
+
+impl SerialForm {
+    /// The series the work already belongs to, or the one picked on the form.
+    pub async fn get(&self) -> Result<Option<String>> {
+        if let Some(link) = self
+            .base
+            .find_all(By::Css("a[href^=\"/series/\"]"))
+            .await?
+            .first()
+        {
+            return Ok(Some(link.text().await?));
+        }
+
+        let existing = self.existing.resolve().await?;
+        if let Some(value) = existing.current_value().await?
+            && !value.is_empty()
+        {
+            return existing.current_innerhtml().await;
+        }
+
+        let title = self.new_title.resolve().await?.value().await?;
+        Ok(Some(title).filter(|t| !t.is_empty()))
+    }
+
+    /// Picks one of the account's series by name, or starts a new one.
+    pub async fn set(&self, series: &str) -> Result<()> {
+        let new_title = self.new_title.resolve().await?;
+        new_title.delete_all().await?;
+
+        if !self
+            .existing
+            .resolve()
+            .await?
+            .select_by_innerhtml(series)
+            .await?
+        {
+            new_title.push_text(series, None).await?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Component)]
 pub struct BackdatingForm {
     base: WebElement,
+
+    #[by(css = "select#work_chapter_attributes_published_at_3i")]
+    day: ElementResolver<DropdownSelector>,
+
+    #[by(css = "select#work_chapter_attributes_published_at_2i")]
+    month: ElementResolver<DropdownSelector>,
+
+    #[by(css = "select#work_chapter_attributes_published_at_1i")]
+    year: ElementResolver<DropdownSelector>,
+}
+
+impl BackdatingForm {
+    pub async fn get(&self) -> Result<Option<String>> {
+        let mut parts = vec![];
+        for select in [&self.year, &self.month, &self.day] {
+            match select.resolve().await?.current_value().await? {
+                Some(value) => parts.push(value.parse::<u32>().unwrap_or_default()),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(format!(
+            "{:04}-{:02}-{:02}",
+            parts[0], parts[1], parts[2]
+        )))
+    }
+
+    pub async fn set(&self, year: u32, month: u32, day: u32) -> Result<()> {
+        for (select, value, what) in [
+            (&self.year, year, "year"),
+            (&self.month, month, "month"),
+            (&self.day, day, "day"),
+        ] {
+            if !select
+                .resolve()
+                .await?
+                .select_by_value(&value.to_string())
+                .await?
+            {
+                bail!("AO3 does not allow backdating to {} {}", what, value);
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Component)]
 pub struct RemixForm {
     base: WebElement,
+
+    #[by(css = "input#work_parent_work_relationships_attributes_0_url")]
+    url: ElementResolver<TextField>,
+
+    #[by(css = "input#work_parent_work_relationships_attributes_0_title")]
+    title: ElementResolver<TextField>,
+
+    #[by(css = "input#work_parent_work_relationships_attributes_0_author")]
+    author: ElementResolver<TextField>,
+
+    #[by(css = "select#work_parent_work_relationships_attributes_0_language_id")]
+    language: ElementResolver<DropdownSelector>,
+
+    #[by(css = "input#work_parent_work_relationships_attributes_0_translation")]
+    translated: ElementResolver<Checkbox>,
+}
+
+impl RemixForm {
+    pub async fn get(&self) -> Result<FicRemix> {
+        let language = self.language.resolve().await?;
+        let language = match language.current_value().await? {
+            Some(value) if !value.is_empty() => language.current_innerhtml().await?,
+            _ => None,
+        };
+
+        Ok(FicRemix {
+            url: self.url.resolve().await?.value().await?,
+            title: self.title.resolve().await?.value().await?,
+            author: self.author.resolve().await?.value().await?,
+            language: language.unwrap_or_default(),
+            translated: self.translated.resolve().await?.state().await?,
+        })
+    }
+
+    pub async fn set(&self, remix: &FicRemix) -> Result<()> {
+        for (field, text) in [
+            (&self.url, &remix.url),
+            (&self.title, &remix.title),
+            (&self.author, &remix.author),
+        ] {
+            let field = field.resolve().await?;
+            field.delete_all().await?;
+            field.push_text(text, None).await?;
+        }
+
+        if !remix.language.is_empty()
+            && !self
+                .language
+                .resolve()
+                .await?
+                .select_by_innerhtml(&remix.language)
+                .await?
+        {
+            bail!("AO3 does not offer the language {:?}", remix.language);
+        }
+
+        self.translated
+            .resolve()
+            .await?
+            .set(remix.translated)
+            .await?;
+
+        Ok(())
+    }
 }
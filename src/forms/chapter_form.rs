@@ -0,0 +1,87 @@
+use thirtyfour::{
+    By, WebElement,
+    components::{Component, ElementResolver},
+};
+
+use crate::{
+    config::FicDetails,
+    forms::{TextField, click_submit, model::PostAction, text_form::TextForm},
+    *,
+};
+
+#[derive(Debug, Clone, Component)]
+pub struct ChapterForm {
+    base: WebElement,
+
+    #[by(css = "input#chapter_title")]
+    title: ElementResolver<TextField>,
+
+    #[by(css = "textarea#chapter_summary")]
+    summary: ElementResolver<TextField>,
+
+    #[by(css = "textarea#chapter_notes")]
+    start_note: ElementResolver<TextField>,
+
+    #[by(css = "textarea#chapter_endnotes")]
+    end_note: ElementResolver<TextField>,
+
+    #[by(css = "input#chapter_wip_length")]
+    total: ElementResolver<TextField>,
+
+    #[by(xpath = ".//textarea[@id='content']/ancestor::fieldset[1]")]
+    pub text: ElementResolver<TextForm>,
+
+    #[by(css = "input[type=\"submit\"]")]
+    submit_buttons: ElementResolver<Vec<WebElement>>,
+}
+
+impl ChapterForm {
+    /// Reads the chapter's preface; empty fields come back as `None`.
+    pub async fn get_details(&self) -> Result<FicDetails> {
+        Ok(FicDetails {
+            title: self.title.resolve().await?.value().await?,
+            summary: Self::optional(&self.summary).await?,
+            start_note: Self::optional(&self.start_note).await?,
+            end_note: Self::optional(&self.end_note).await?,
+            ..Default::default()
+        })
+    }
+
+    /// Fills out the chapter's preface and text. Notes are set directly, so
+    /// their show/hide checkboxes are left alone.
+    pub async fn fill_out(&self, chapter: &FicDetails, total: u64, html: &str) -> Result<()> {
+        let title = self.title.resolve().await?;
+        title.delete_all().await?;
+        title.push_text(&chapter.title, None).await?;
+
+        for (field, text) in [
+            (&self.summary, &chapter.summary),
+            (&self.start_note, &chapter.start_note),
+            (&self.end_note, &chapter.end_note),
+        ] {
+            let text = text.as_deref().unwrap_or_default();
+            field.resolve().await?.set_value(text).await?;
+        }
+
+        let total = if total == 0 {
+            "?".to_string()
+        } else {
+            total.to_string()
+        };
+        let tot = self.total.resolve().await?;
+        tot.delete_all().await?;
+        tot.push_text(&total, None).await?;
+
+        self.text.resolve().await?.set_html(html).await
+    }
+
+    pub async fn submit(&self, action: PostAction) -> Result<()> {
+        let buttons = self.submit_buttons.resolve().await?;
+        click_submit(buttons, action, "chapter").await
+    }
+
+    async fn optional(field: &ElementResolver<TextField>) -> Result<Option<String>> {
+        let text = field.resolve().await?.value().await?;
+        Ok(Some(text).filter(|t| !t.is_empty()))
+    }
+}
@@ -0,0 +1,47 @@
+use thirtyfour::{
+    By, WebElement,
+    components::{Component, ElementResolver},
+};
+
+use crate::{
+    forms::{Checkbox, TextField},
+    secret::Credentials,
+    *,
+};
+
+/// The login form in the header dropdown.
+#[derive(Debug, Clone, Component)]
+pub struct LoginForm {
+    base: WebElement,
+
+    #[by(css = "input#user_session_login_small")]
+    username: ElementResolver<TextField>,
+
+    #[by(css = "input#user_session_password_small")]
+    password: ElementResolver<TextField>,
+
+    #[by(css = "input#user_session_remember_me_small")]
+    remember_me: ElementResolver<Checkbox>,
+
+    #[by(css = "[type=\"submit\"]")]
+    submit: ElementResolver<WebElement>,
+}
+
+impl LoginForm {
+    /// Fills in the credentials, asks to be remembered, and submits.
+    pub async fn log_in(&self, credentials: &Credentials) -> Result<()> {
+        let username = self.username.resolve().await?;
+        username.delete_all().await?;
+        username.push_text(&credentials.username, None).await?;
+
+        let password = self.password.resolve().await?;
+        password.delete_all().await?;
+        password
+            .push_text(credentials.password.expose(), None)
+            .await?;
+
+        self.remember_me.resolve().await?.set(true).await?;
+        self.submit.resolve().await?.click().await?;
+        Ok(())
+    }
+}
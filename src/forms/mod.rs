@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use indexmap::{IndexMap, IndexSet};
 use rootcause::prelude::*;
@@ -9,13 +9,30 @@ use thirtyfour::{
 };
 
 pub mod associations_form;
+pub mod chapter_form;
+pub mod login_form;
 pub mod model;
 pub mod preface_form;
+pub mod rejection;
 pub mod tags_form;
+pub mod text_form;
 pub mod work_form;
 
-use crate::*;
-use model::UseByValue;
+use crate::{driver::poll_until, exit::Failure, render::unescape_html, *};
+use model::{PostAction, UseByValue};
+
+/// Clicks the button among a form's `buttons` that performs `action`.
+pub async fn click_submit(buttons: Vec<WebElement>, action: PostAction, form: &str) -> Result<()> {
+    let value = action.as_value();
+    for button in buttons {
+        if button.value().await?.as_ref() == Some(&value) {
+            button.scroll_into_view().await?;
+            button.click().await?;
+            return Ok(());
+        }
+    }
+    bail!("No {:?} button on the {} form", value, form)
+}
 
 #[derive(Debug, Clone, Component)]
 pub struct DropdownSelector {
@@ -32,7 +49,17 @@ impl DropdownSelector {
                 return Ok(Some(opt.value().await?.unwrap_or_default()));
             }
         }
-        return Ok(None);
+        Ok(None)
+    }
+
+    pub async fn current_innerhtml(&self) -> Result<Option<String>> {
+        let selected = Some("true".to_string());
+        for opt in self.options.resolve().await? {
+            if opt.prop("selected").await? == selected {
+                return Ok(Some(opt.inner_html().await?));
+            }
+        }
+        Ok(None)
     }
 
     pub async fn list_all_by_value(&self) -> Result<Vec<(String, bool)>> {
@@ -65,7 +92,7 @@ impl DropdownSelector {
         let ref_value = val.as_value();
         for option in options {
             let value = option.value().await?;
-            if (&value).as_ref() == Some(&ref_value) {
+            if value.as_ref() == Some(&ref_value) {
                 option.click().await?;
                 return Ok(true);
             }
@@ -95,7 +122,7 @@ impl DropdownSelector {
 
         for val in vals {
             self.select_by_value(val).await?;
-            tokio::time::sleep(Duration::from_millis(300));
+            tokio::time::sleep(Duration::from_millis(300)).await;
         }
 
         self.select_by_value(&current).await?;
@@ -159,6 +186,16 @@ impl CheckboxesByValue {
         Ok(res)
     }
 
+    /// The values of every ticked box, in page order.
+    pub async fn checked_values(&self) -> Result<Vec<String>> {
+        Ok(self
+            .all_by_values()
+            .await?
+            .into_iter()
+            .filter_map(|(value, state)| state.then_some(value))
+            .collect())
+    }
+
     pub async fn set_one_to<T: UseByValue>(&self, id: &T, state: bool) -> Result<bool> {
         let id = id.as_value();
         for elem in self.boxes.resolve().await? {
@@ -183,14 +220,14 @@ impl CheckboxesByValue {
 
         println_async!("CheckboxesByValue: {:#?}", curr);
 
-        self.set_all_to(false).await;
-        tokio::time::sleep(Duration::from_millis(100));
-        self.set_all_to(true).await;
-        tokio::time::sleep(Duration::from_millis(100));
-        self.set_all_to(false).await;
+        self.set_all_to(false).await?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        self.set_all_to(true).await?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        self.set_all_to(false).await?;
 
         for (val, state) in curr {
-            self.set_one_to(&val, state);
+            self.set_one_to(&val, state).await?;
         }
 
         Ok(())
@@ -216,16 +253,42 @@ impl TextField {
         self.base.clear().await?;
         Ok(())
     }
+
+    pub async fn value(&self) -> Result<String> {
+        Ok(self.base.value().await?.unwrap_or_default())
+    }
+
+    /// Replaces the contents in one go, for texts too long to type out.
+    pub async fn set_value(&self, text: &str) -> Result<()> {
+        self.base
+            .handle
+            .execute(
+                r#"
+                arguments[0].value = arguments[1];
+                arguments[0].dispatchEvent(new Event("input", { bubbles: true }));
+                arguments[0].dispatchEvent(new Event("change", { bubbles: true }));
+                "#,
+                vec![self.base.to_json()?, serde_json::Value::from(text)],
+            )
+            .await?;
+        Ok(())
+    }
 }
 
+/// How long AO3 gets to suggest an entry before it is typed in as is.
+/// Freeform tags have no suggestions, so this is kept well below the
+/// element timeout.
+pub const SUGGESTION_WAIT: Duration = Duration::from_secs(5);
+
 #[derive(Debug, Clone, Component)]
 pub struct Autocomplete {
     base: WebElement,
     #[by(css = "li.input > input.text[type=\"text\"]")]
     text_field: ElementResolver<TextField>,
-    // bugged: this just doesn't work for some reason
-    // #[by(allow_empty, css = "li.added.tag")]
-    // entries: ElementResolver<Vec<AutocompleteEntry>>,
+    // Looked up once and without waiting, since there may be none; entries
+    // come and go, so it is always resolved afresh.
+    #[by(css = "li.added.tag", allow_empty, nowait)]
+    entries: ElementResolver<Vec<AutocompleteEntry>>,
 }
 
 impl Autocomplete {
@@ -251,22 +314,57 @@ impl Autocomplete {
     }
 
     pub async fn entries(&self) -> Result<Vec<AutocompleteEntry>> {
-        // Ok(self.entries.resolve().await?)
-        let all = self.base.find_all(By::Css("li.added.tag")).await?;
-        let mut res = vec![];
-        for e in all {
-            res.push(AutocompleteEntry::from(e))
+        Ok(self.entries.resolve_force().await?)
+    }
+
+    /// Types `entry` in and picks AO3's suggestion of exactly that name, or
+    /// adds it as typed when there is none. Returns whether it was suggested.
+    pub async fn add_entry(&self, entry: &str) -> Result<bool> {
+        let before = self.entries().await?.len();
+        let field = self.text_field.resolve().await?;
+        field.push_text(entry, None).await?;
+
+        let suggested = self.pick_suggestion(entry).await?;
+        if !suggested {
+            field.push_text("", Some(Key::Tab)).await?;
         }
-        Ok(res)
+
+        if !poll_until(&self.base.handle, async || {
+            Ok(self.entries().await?.len() > before)
+        })
+        .await?
+        {
+            bail!("{:?} was never added", entry);
+        }
+        Ok(suggested)
     }
 
-    pub async fn add_entry(&self, entry: &str) -> Result<()> {
-        self.text_field
-            .resolve()
-            .await?
-            .push_text(entry, Some(Key::Tab))
-            .await?;
-        Ok(())
+    /// Waits a little for the suggestion list to show, and clicks the
+    /// suggestion that is the same entry as `entry` if there is one. A list
+    /// without it ends the wait early.
+    async fn pick_suggestion(&self, entry: &str) -> Result<bool> {
+        let key = entry_key(entry);
+        let start = Instant::now();
+        while start.elapsed() < SUGGESTION_WAIT {
+            let suggestions = self
+                .base
+                .find_all(By::Css("li.input .autocomplete li"))
+                .await?;
+            for suggestion in &suggestions {
+                // The list is redrawn as AO3 answers, so items go stale.
+                if let Ok(text) = suggestion.text().await
+                    && entry_key(&text) == key
+                {
+                    suggestion.click().await?;
+                    return Ok(true);
+                }
+            }
+            if !suggestions.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+        Ok(false)
     }
 
     pub async fn list_entries(&self) -> Result<Vec<String>> {
@@ -275,7 +373,6 @@ impl Autocomplete {
         let entries = self.entries().await?;
 
         for entry in entries {
-            println_async!("getting entry name");
             let name = entry.entry_name().await?;
             res.push(name);
         }
@@ -291,26 +388,45 @@ impl Autocomplete {
     }
 
     pub async fn delete_entry(&self, name: &str) -> Result<()> {
+        let key = entry_key(name);
         for entry in self.entries().await? {
-            if entry.entry_name().await? == name {
+            if entry_key(&entry.entry_name().await?) == key {
                 entry.delete().await?;
             }
         }
+        let gone = async || {
+            let names = self.list_entries().await?;
+            Ok(!names.iter().any(|e| entry_key(e) == key))
+        };
+        if !poll_until(&self.base.handle, gone).await? {
+            bail!("{:?} was never removed", name);
+        }
         Ok(())
     }
 
-    pub async fn set(&self, strings: &IndexSet<String>) -> Result<()> {
-        let mut all = IndexSet::<String>::from_iter(self.list_entries().await?);
+    /// Makes the entries exactly `strings`. Returns the entries it added,
+    /// and whether AO3 suggested each.
+    /// Names are compared the way AO3 compares tags, so an entry differing
+    /// only in case or spacing is left alone.
+    pub async fn set(&self, strings: &IndexSet<String>) -> Result<Vec<(String, bool)>> {
+        let current = self.list_entries().await?;
+        let wanted: IndexSet<String> = strings.iter().map(|s| entry_key(s)).collect();
+        let present: IndexSet<String> = current.iter().map(|s| entry_key(s)).collect();
 
-        for entry in all.difference(strings) {
-            self.delete_entry(entry).await?;
+        for entry in &current {
+            if !wanted.contains(&entry_key(entry)) {
+                self.delete_entry(entry).await?;
+            }
         }
 
-        for entry in strings.difference(&all) {
-            self.add_entry(entry).await?;
+        let mut added = vec![];
+        for entry in strings {
+            if !present.contains(&entry_key(entry)) {
+                added.push((entry.clone(), self.add_entry(entry).await?));
+            }
         }
 
-        Ok(())
+        Ok(added)
     }
 }
 
@@ -322,15 +438,9 @@ pub struct AutocompleteEntry {
 }
 
 impl AutocompleteEntry {
+    /// The entry's text, without its delete button and with entities decoded.
     pub async fn entry_name(&self) -> Result<String> {
-        let re = regex::Regex::new(r#"(.*)\s+<span class="delete">"#).unwrap();
-        if let Some(cap) = re.captures(&(self.base.inner_html().await?)) {
-            return Ok(cap
-                .get(1)
-                .map(|m| m.as_str().to_string())
-                .unwrap_or_default());
-        }
-        return Ok("".to_string());
+        Ok(entry_text(&self.base.inner_html().await?))
     }
 
     pub async fn delete(&self) -> Result<()> {
@@ -339,9 +449,87 @@ impl AutocompleteEntry {
     }
 }
 
+fn entry_text(html: &str) -> String {
+    let text = match html.find("<span class=\"delete\"") {
+        Some(at) => &html[..at],
+        None => html,
+    };
+    let tags = regex::Regex::new(r"<[^>]*>").unwrap();
+    unescape_html(&tags.replace_all(text, ""))
+        .trim()
+        .to_string()
+}
+
+/// What AO3 goes by when telling tags apart: case and runs of whitespace
+/// do not count.
+pub fn entry_key(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// The byline: a multiple select of the account's pseuds, or only the name
+/// when the account has a single pseud.
 #[derive(Debug, Clone, Component)]
 pub struct PseudsSelector {
     base: WebElement,
 }
 
-impl PseudsSelector {}
+impl PseudsSelector {
+    /// Every pseud on offer, and whether it is selected.
+    pub async fn list(&self) -> Result<Vec<(String, bool)>> {
+        let options = self.base.find_all(By::Css("select option")).await?;
+        if options.is_empty() {
+            return Ok(vec![(self.base.text().await?.trim().to_string(), true)]);
+        }
+        let mut res = vec![];
+        for opt in options {
+            res.push((
+                opt.text().await?.trim().to_string(),
+                opt.is_selected().await?,
+            ));
+        }
+        Ok(res)
+    }
+
+    pub async fn get(&self) -> Result<IndexSet<String>> {
+        Ok(self
+            .list()
+            .await?
+            .into_iter()
+            .filter_map(|(pseud, selected)| selected.then_some(pseud))
+            .collect())
+    }
+
+    /// Selects exactly `pseuds`, failing if the account lacks any of them.
+    pub async fn set(&self, pseuds: &IndexSet<String>) -> Result<()> {
+        let offered: Vec<String> = self.list().await?.into_iter().map(|(p, _)| p).collect();
+        let missing: Vec<&String> = pseuds.iter().filter(|p| !offered.contains(p)).collect();
+        if !missing.is_empty() {
+            return Err(Failure::InvalidSpec.report(format!(
+                "The account has no pseud {:?}, only {:?}",
+                missing, offered
+            )));
+        }
+
+        for opt in self.base.find_all(By::Css("select option")).await? {
+            let wanted = pseuds.contains(opt.text().await?.trim());
+            // Clicking an option of a multiple select toggles it.
+            if opt.is_selected().await? != wanted {
+                opt.click().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_entry_text() {
+    let html = "Bleach (Anime &amp; Manga) <span class=\"delete\"><a href=\"#\">×</a></span>";
+    assert_eq!(entry_text(html), "Bleach (Anime & Manga)");
+    assert_eq!(
+        entry_key("  Bleach (Anime &  MANGA) "),
+        entry_key("bleach (anime & manga)")
+    );
+}
@@ -1,14 +1,10 @@
-use std::process::Stdio;
-
-use crate::*;
+use crate::{
+    render::{Diagnostic, check_html, diagnostics_report, markdown, typst},
+    *,
+};
 use clap::Parser;
-use rootcause::report;
 use serde::{Deserialize, Serialize};
 use strum::VariantArray;
-use tokio::{
-    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
-    process::Command,
-};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, Parser, VariantArray)]
 pub enum FileFormat {
@@ -20,40 +16,15 @@ pub enum FileFormat {
 
 impl FileFormat {
     pub async fn to_html(self, string: &str) -> Result<String> {
-        match self {
-            Self::Typst => todo!(),
-            Self::Markdown => Self::parse_markdown(string).await,
-            Self::HTML => Ok(string.to_string()),
-        }
+        self.render(string).map_err(|d| diagnostics_report(&d))
     }
 
-    pub async fn parse_markdown(string: &str) -> Result<String> {
-        let mut pandoc = Command::new("pandoc")
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()?;
-
-        let mut cin = pandoc
-            .stdin
-            .take()
-            .ok_or_else(|| report!("No stdin on pandoc"))?;
-
-        let cout = pandoc
-            .stdout
-            .take()
-            .ok_or_else(|| report!("No stdout on pandoc"))?;
-
-        cin.write_all(&string.as_bytes()).await?;
-        cin.shutdown().await?;
-
-        let mut res = String::new();
-        let mut cout = BufReader::new(cout);
-        cout.read_to_string(&mut res).await?;
-
-        pandoc.kill().await?;
-
-        Ok(res)
+    pub fn render(self, string: &str) -> std::result::Result<String, Vec<Diagnostic>> {
+        match self {
+            Self::Typst => typst::to_html(string),
+            Self::Markdown => markdown::to_html(string),
+            Self::HTML => check_html(string).map(|()| string.to_string()),
+        }
     }
 }
 
@@ -118,13 +89,22 @@ pub enum AgeRating {
     Explicit,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, VariantArray)]
+pub enum PostAction {
+    Preview,
+    #[serde(rename = "Save As Draft")]
+    Draft,
+    Post,
+    Update,
+}
+
 pub fn enum_as_string<T: Serialize>(e: &T) -> String {
     let mut buf = String::new();
     e.serialize(toml::ser::ValueSerializer::new(&mut buf))
         .unwrap();
     let val = buf.parse::<toml::Value>().unwrap();
     match val {
-        toml::Value::String(s) => return s,
+        toml::Value::String(s) => s,
         val => panic!("Not a string: {}", val),
     }
 }
@@ -133,6 +113,11 @@ pub trait UseByValue {
     fn as_value(&self) -> String;
 }
 
+/// The variant whose form value is `value`, the inverse of `as_value`.
+pub fn from_value<T: UseByValue + VariantArray + Copy>(value: &str) -> Option<T> {
+    T::VARIANTS.iter().copied().find(|v| v.as_value() == value)
+}
+
 impl UseByValue for AgeRating {
     fn as_value(&self) -> String {
         enum_as_string(self)
@@ -151,6 +136,12 @@ impl UseByValue for FicCategory {
     }
 }
 
+impl UseByValue for PostAction {
+    fn as_value(&self) -> String {
+        enum_as_string(self)
+    }
+}
+
 impl UseByValue for String {
     fn as_value(&self) -> String {
         self.clone()
@@ -21,7 +21,7 @@ pub struct PrefaceForm {
     #[by(css = "dd.summary > textarea")]
     summary: ElementResolver<TextField>,
 
-    #[by(css = "dd.byline > select")]
+    #[by(css = "dd.byline:not(.coauthors)")]
     pseuds: ElementResolver<PseudsSelector>,
 
     #[by(css = "dd.byline.coauthors")]
@@ -32,15 +32,18 @@ pub struct PrefaceForm {
 }
 
 impl PrefaceForm {
-    pub async fn set_all(&self, preface: &FicDetails) -> Result<()> {
+    /// `pseuds` go in the byline; without any, AO3's default one is kept.
+    pub async fn set_all(&self, preface: &FicDetails, pseuds: &IndexSet<String>) -> Result<()> {
         self.set_title(&preface.title).await?;
 
-        self.pseuds.resolve().await?;
+        if !pseuds.is_empty() {
+            self.pseuds.resolve().await?.set(pseuds).await?;
+        }
 
         self.set_cocreators(&preface.co_authors).await?;
 
         if let Some(summary) = &preface.summary {
-            self.set_summary(&summary).await?;
+            self.set_summary(summary).await?;
         }
 
         let notes = self.notes.resolve().await?;
@@ -54,6 +57,22 @@ impl PrefaceForm {
         Ok(())
     }
 
+    /// Reads the preface back; the work URL and text file are not on the form.
+    pub async fn get_details(&self) -> Result<FicDetails> {
+        let summary = self.summary.resolve().await?.value().await?;
+        let notes = self.notes.resolve().await?;
+
+        Ok(FicDetails {
+            title: self.title.resolve().await?.value().await?,
+            author_pseud: self.pseuds.resolve().await?.get().await?,
+            co_authors: self.coauthors.resolve().await?.get().await?,
+            summary: Some(summary).filter(|s| !s.is_empty()),
+            start_note: notes.get_start().await?,
+            end_note: notes.get_end().await?,
+            ..Default::default()
+        })
+    }
+
     pub async fn set_cocreators(&self, creators: &IndexSet<String>) -> Result<()> {
         println_async!("resloving");
         let x = self.coauthors.resolve().await?;
@@ -88,17 +107,25 @@ pub struct CoAuthors {
 }
 
 impl CoAuthors {
+    pub async fn get(&self) -> Result<IndexSet<String>> {
+        if !self.has_coauthors.resolve().await?.state().await? {
+            return Ok(IndexSet::new());
+        }
+        let authors = self.coauthors.resolve().await?.list_entries().await?;
+        Ok(authors.into_iter().collect())
+    }
+
     pub async fn set(&self, authors: &IndexSet<String>) -> Result<()> {
         let has = self.has_coauthors.resolve().await?;
         let open = has.state().await?;
-        if authors.len() == 0 && open {
+        if authors.is_empty() && open {
             self.coauthors.resolve().await?.delete_all().await?;
             has.set(false).await?;
-        } else if authors.len() != 0 && !open {
+        } else if !authors.is_empty() && !open {
             has.set(true).await?;
             let x = self.coauthors.resolve().await?;
             x.set(authors).await?;
-        } else if authors.len() != 0 && open {
+        } else if !authors.is_empty() && open {
             self.coauthors.resolve().await?.set(authors).await?;
         }
 
@@ -121,6 +148,28 @@ pub struct Notes {
 }
 
 impl Notes {
+    pub async fn get_start(&self) -> Result<Option<String>> {
+        let check = self.has_startnote.resolve().await?;
+        self.get(&self.startnote, check).await
+    }
+
+    pub async fn get_end(&self) -> Result<Option<String>> {
+        let check = self.has_endnote.resolve().await?;
+        self.get(&self.endnote, check).await
+    }
+
+    async fn get(
+        &self,
+        text: &ElementResolver<TextField>,
+        check: Checkbox,
+    ) -> Result<Option<String>> {
+        if !check.state().await? {
+            return Ok(None);
+        }
+        let note = text.resolve().await?.value().await?;
+        Ok(Some(note).filter(|n| !n.is_empty()))
+    }
+
     pub async fn set_start(&self, note: Option<&str>) -> Result<()> {
         let check = self.has_startnote.resolve().await?;
         self.set(&self.startnote, check, note).await
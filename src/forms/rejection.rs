@@ -0,0 +1,157 @@
+//! The errors AO3 lists in `div#error` when it refuses to save a form.
+
+use std::fmt;
+
+use rootcause::Report;
+use thirtyfour::{By, WebElement};
+
+use crate::{Result, exit::Failure};
+
+/// Words in AO3's error messages, and the spec fields they are about.
+/// Fields starting with `.` belong to the work or chapter being saved.
+/// Earlier entries win, so "Summary must be less than 1250 characters"
+/// is about the summary and not the characters.
+const FIELDS: &[(&str, &str)] = &[
+    ("must not add up to more than", "tags"),
+    ("parent work", "remix.url"),
+    ("end note", ".end_note"),
+    ("note", ".start_note"),
+    ("summary", ".summary"),
+    ("title", ".title"),
+    ("co-creator", ".co_authors"),
+    ("pseud", ".author_pseud"),
+    ("creator", ".author_pseud"),
+    ("content", ".file"),
+    ("fandom", "tags.fandoms"),
+    ("relationship", "tags.relationships"),
+    ("rating", "tags.rating"),
+    ("warning", "tags.warnings"),
+    ("categor", "tags.categories"),
+    ("additional tag", "tags.other"),
+    ("character", "tags.characters"),
+    ("language", "meta.language"),
+    ("series", "meta.in_series"),
+    ("collection", "meta.challenges"),
+    ("recipient", "meta.gift_to"),
+    ("gift", "meta.gift_to"),
+    ("skin", "meta.work_skin"),
+    ("number of chapters", "meta.total_chapters"),
+    ("publication date", "meta.publication_date"),
+];
+
+/// One problem AO3 found with a submitted form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormError {
+    pub message: String,
+    /// The spec field to fix, e.g. `tags.fandoms`, when the message names one.
+    pub field: Option<String>,
+}
+
+impl FormError {
+    /// `scope` is where the form's own details live in the spec: `fic` for
+    /// a work, or `chapters.<key>` for a chapter.
+    pub fn new(message: &str, scope: &str) -> Self {
+        let lower = message.to_lowercase();
+        let field = FIELDS
+            .iter()
+            .find(|(needle, _)| lower.contains(needle))
+            .map(|(_, field)| match field.strip_prefix('.') {
+                Some(own) => format!("{scope}.{own}"),
+                None => field.to_string(),
+            });
+        Self {
+            message: message.trim().to_string(),
+            field,
+        }
+    }
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{}: {}", field, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Why AO3 refused to save a work or chapter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    pub errors: Vec<FormError>,
+}
+
+impl Rejection {
+    /// Reads the messages of an error list, or its whole text when AO3
+    /// gave a single message without a list.
+    pub async fn read(list: &WebElement, scope: &str) -> Result<Self> {
+        let mut messages = vec![];
+        for item in list.find_all(By::Css("li")).await? {
+            messages.push(item.text().await?);
+        }
+        if messages.is_empty() {
+            messages.push(list.text().await?);
+        }
+        Ok(Self {
+            errors: messages
+                .iter()
+                .filter(|m| !m.trim().is_empty())
+                .map(|m| FormError::new(m, scope))
+                .collect(),
+        })
+    }
+
+    /// A report that exits as `Failure::Rejected`, with each error attached.
+    pub fn report(self) -> Report {
+        let errors = self.errors.clone();
+        errors
+            .into_iter()
+            .fold(Report::new(self), Report::attach)
+            .context(Failure::Rejected)
+            .into_dyn_any()
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.errors.as_slice() {
+            [only] => write!(f, "AO3 refused the form: {}", only.message),
+            errors => write!(f, "AO3 refused the form with {} errors", errors.len()),
+        }
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+#[test]
+fn test_form_error_fields() {
+    let field = |message| FormError::new(message, "chapters.02").field;
+    assert_eq!(
+        field("Please add all required tags. Fandom is missing."),
+        Some("tags.fandoms".to_string())
+    );
+    assert_eq!(
+        field("Summary must be less than 1250 characters long."),
+        Some("chapters.02.summary".to_string())
+    );
+    assert_eq!(
+        field("These pseuds are invalid: nobody"),
+        Some("chapters.02.author_pseud".to_string())
+    );
+    assert_eq!(
+        field("End notes must be less than 5000 characters long."),
+        Some("chapters.02.end_note".to_string())
+    );
+    assert_eq!(field("Something went wrong."), None);
+
+    let rejection = Rejection {
+        errors: vec![FormError::new("Please fill in at least one fandom.", "fic")],
+    };
+    let report = rejection.clone().report();
+    assert_eq!(crate::exit::exit_code(&report), 6);
+    assert!(
+        report
+            .iter_reports()
+            .any(|r| r.downcast_current_context::<Rejection>() == Some(&rejection))
+    );
+}
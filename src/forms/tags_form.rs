@@ -1,6 +1,7 @@
 use std::collections::BTreeSet;
 
 use indexmap::IndexSet;
+use rootcause::bail;
 use strum::VariantArray;
 use thirtyfour::{
     By, WebElement,
@@ -9,7 +10,8 @@ use thirtyfour::{
 
 use crate::{
     config::FicTags,
-    forms::model::{AgeRating, ArchiveWarning, FicCategory},
+    driver::{TagStatus, tag_status},
+    forms::model::{AgeRating, ArchiveWarning, FicCategory, UseByValue, from_value},
     forms::{Autocomplete, CheckboxesByValue, DropdownSelector},
     *,
 };
@@ -92,14 +94,20 @@ impl TagsForm {
         Ok(())
     }
 
-    pub async fn set_all(&self, tags: &FicTags) -> Result<()> {
+    /// With `look_up`, tags AO3 did not suggest are looked up on the archive.
+    pub async fn set_all(&self, tags: &FicTags, look_up: bool) -> Result<()> {
         self.set_work_rating(tags.rating).await?;
         self.set_warnings(&tags.warnings).await?;
-        self.set_fandoms(&tags.fandoms).await?;
+        let fandoms = self.set_fandoms(&tags.fandoms).await?;
         self.set_categories(&tags.categories).await?;
-        self.set_relationships(&tags.relationships).await?;
-        self.set_characters(&tags.characters).await?;
-        self.set_other_tags(&tags.other).await?;
+        let relationships = self.set_relationships(&tags.relationships).await?;
+        let characters = self.set_characters(&tags.characters).await?;
+        let other = self.set_other_tags(&tags.other).await?;
+
+        report_tags("Fandom", fandoms, true, look_up).await;
+        report_tags("Relationship", relationships, true, look_up).await;
+        report_tags("Character", characters, false, look_up).await;
+        report_tags("Tag", other, false, look_up).await;
 
         Ok(())
     }
@@ -107,23 +115,51 @@ impl TagsForm {
     pub async fn get_tags(&self) -> Result<FicTags> {
         let mut res = FicTags::default();
 
+        if let Some(rating) = self.work_rating.resolve().await?.current_value().await? {
+            res.rating = from_value(&rating).unwrap_or_default();
+        }
+
+        let categories = self.categories.resolve().await?.checked_values().await?;
+        res.categories = categories.iter().filter_map(|c| from_value(c)).collect();
+
+        let warnings = self.warnings.resolve().await?.checked_values().await?;
+        res.warnings = warnings.iter().filter_map(|w| from_value(w)).collect();
+        if res.warnings.is_empty() {
+            res.warnings.insert(ArchiveWarning::CNTUAW);
+        }
+
+        for (field, set) in [
+            (&self.fandoms, &mut res.fandoms),
+            (&self.relationships, &mut res.relationships),
+            (&self.characters, &mut res.characters),
+            (&self.other_tags, &mut res.other),
+        ] {
+            set.extend(field.resolve().await?.list_entries().await?);
+        }
+
         Ok(res)
     }
 
     pub async fn set_work_rating(&self, age_rating: AgeRating) -> Result<()> {
-        self.work_rating
+        if !self
+            .work_rating
             .resolve()
             .await?
             .select_by_value(&age_rating)
-            .await?;
+            .await?
+        {
+            bail!("The form has no rating {:?}", age_rating.as_value());
+        }
         Ok(())
     }
 
     pub async fn set_categories(&self, cats: impl IntoIterator<Item = &FicCategory>) -> Result<()> {
         let boxes = self.categories.resolve().await?;
-        boxes.set_all_to(false);
+        boxes.set_all_to(false).await?;
         for cat in cats {
-            boxes.set_one_to(cat, true).await?;
+            if !boxes.set_one_to(cat, true).await? {
+                bail!("The form has no category {:?}", cat.as_value());
+            }
         }
 
         Ok(())
@@ -132,29 +168,80 @@ impl TagsForm {
     pub async fn set_warnings(&self, warnings: &BTreeSet<ArchiveWarning>) -> Result<()> {
         let boxes = self.warnings.resolve().await?;
         boxes.set_all_to(false).await?;
-        if warnings.len() == 0 {
-            boxes.set_one_to(&ArchiveWarning::CNTUAW, true).await?;
+        let default = BTreeSet::from([ArchiveWarning::CNTUAW]);
+        let warnings = if warnings.is_empty() {
+            &default
         } else {
-            for warn in warnings {
-                boxes.set_one_to(warn, true).await?;
+            warnings
+        };
+        for warn in warnings {
+            if !boxes.set_one_to(warn, true).await? {
+                bail!("The form has no warning {:?}", warn.as_value());
             }
         }
         Ok(())
     }
 
-    pub async fn set_fandoms(&self, fandoms: &IndexSet<String>) -> Result<()> {
+    pub async fn set_fandoms(&self, fandoms: &IndexSet<String>) -> Result<Vec<(String, bool)>> {
         self.fandoms.resolve().await?.set(fandoms).await
     }
 
-    pub async fn set_characters(&self, characters: &IndexSet<String>) -> Result<()> {
+    pub async fn set_characters(
+        &self,
+        characters: &IndexSet<String>,
+    ) -> Result<Vec<(String, bool)>> {
         self.characters.resolve().await?.set(characters).await
     }
 
-    pub async fn set_relationships(&self, relationships: &IndexSet<String>) -> Result<()> {
+    pub async fn set_relationships(
+        &self,
+        relationships: &IndexSet<String>,
+    ) -> Result<Vec<(String, bool)>> {
         self.relationships.resolve().await?.set(relationships).await
     }
 
-    pub async fn set_other_tags(&self, other: &IndexSet<String>) -> Result<()> {
+    pub async fn set_other_tags(&self, other: &IndexSet<String>) -> Result<Vec<(String, bool)>> {
         self.other_tags.resolve().await?.set(other).await
     }
 }
+
+/// Says on stderr what AO3 will make of each added tag. Fandoms and
+/// relationships are meant to be canonical, so anything else about them
+/// is a warning.
+async fn report_tags(kind: &str, added: Vec<(String, bool)>, warn: bool, look_up: bool) {
+    for (tag, suggested) in added {
+        // AO3 only suggests canonical tags.
+        let status = if suggested {
+            Ok(TagStatus::Canonical)
+        } else if look_up {
+            tag_status(&tag).await
+        } else {
+            if warn {
+                eprintln_async!(
+                    "Warning: {kind} {tag:?} was not suggested by AO3, it may not be canonical"
+                );
+            }
+            continue;
+        };
+        let (message, bad) = match status {
+            Ok(TagStatus::Canonical) => (format!("{kind} {tag:?} is canonical"), false),
+            Ok(TagStatus::Synonym(canonical)) => (
+                format!("{kind} {tag:?} is a synonym, AO3 will file it under {canonical:?}"),
+                true,
+            ),
+            Ok(TagStatus::Unwrangled) => (
+                format!("{kind} {tag:?} is not a canonical tag, AO3 will keep it as free text"),
+                true,
+            ),
+            Err(e) => (
+                format!("Cannot tell whether {kind} {tag:?} is canonical: {e}"),
+                true,
+            ),
+        };
+        if warn && bad {
+            eprintln_async!("Warning: {}", message);
+        } else {
+            eprintln_async!("{}", message);
+        }
+    }
+}
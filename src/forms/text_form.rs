@@ -0,0 +1,32 @@
+use thirtyfour::{
+    By, WebElement,
+    components::{Component, ElementResolver},
+};
+
+use crate::{forms::TextField, *};
+
+#[derive(Debug, Clone, Component)]
+pub struct TextForm {
+    base: WebElement,
+
+    #[by(css = "ul.rtf-html-switch a.html-link")]
+    html_mode: ElementResolver<WebElement>,
+
+    #[by(css = "textarea#content")]
+    content: ElementResolver<TextField>,
+}
+
+impl TextForm {
+    pub async fn set_html(&self, html: &str) -> Result<()> {
+        let html_mode = self.html_mode.resolve().await?;
+        if html_mode.is_clickable().await? {
+            html_mode.click().await?;
+        }
+
+        self.content.resolve().await?.set_value(html).await
+    }
+
+    pub async fn html(&self) -> Result<String> {
+        self.content.resolve().await?.value().await
+    }
+}
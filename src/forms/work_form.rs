@@ -1,11 +1,21 @@
+use indexmap::IndexSet;
 use thirtyfour::{
     By, WebElement,
     components::{Component, ElementResolver},
 };
 
+use rootcause::bail;
+
 use crate::{
     config::Fanfiction,
-    forms::{associations_form::AssociationsForm, tags_form::TagsForm},
+    forms::{
+        associations_form::AssociationsForm,
+        click_submit,
+        model::{FileFormat, PostAction},
+        tags_form::TagsForm,
+        text_form::TextForm,
+    },
+    plan::{as_on_form, form_mismatches},
 };
 use crate::{forms::preface_form::PrefaceForm, *};
 
@@ -21,12 +31,104 @@ pub struct WorkForm {
 
     #[by(css = "fieldset#associations")]
     pub associations: ElementResolver<AssociationsForm>,
+
+    #[by(css = "fieldset.work.text")]
+    pub text: ElementResolver<TextForm>,
+
+    #[by(css = "input[type=\"submit\"]")]
+    submit_buttons: ElementResolver<Vec<WebElement>>,
 }
 
 impl WorkForm {
-    pub async fn fill_out(&self, fic: &Fanfiction) -> Result<()> {
-        self.tags.resolve().await?.set_all(&fic.tags).await?;
-        self.preface.resolve().await?.set_all(&fic.fic).await?;
-        Ok(())
+    /// Fills out every field, then reads them back so that nothing which
+    /// failed to stick gets submitted.
+    pub async fn fill_out(
+        &self,
+        fic: &Fanfiction,
+        pseuds: &IndexSet<String>,
+        check_tags: bool,
+    ) -> Result<()> {
+        self.tags
+            .resolve()
+            .await?
+            .set_all(&fic.tags, check_tags)
+            .await?;
+        self.preface
+            .resolve()
+            .await?
+            .set_all(&fic.fic, pseuds)
+            .await?;
+        self.associations.resolve().await?.set_all(fic).await?;
+
+        // The edit form of a chaptered work has no text; chapters are edited
+        // on their own pages.
+        let mut html = None;
+        if let Some(file) = fic.work_file()
+            && self.has_text().await?
+        {
+            let rendered = fic.render_file(file).await?;
+            self.text.resolve().await?.set_html(&rendered).await?;
+            html = Some(rendered);
+        }
+
+        self.verify(fic, pseuds, html.as_deref()).await
+    }
+
+    /// Fails listing every field whose value on the form is not the spec's.
+    pub async fn verify(
+        &self,
+        fic: &Fanfiction,
+        pseuds: &IndexSet<String>,
+        html: Option<&str>,
+    ) -> Result<()> {
+        let expected = as_on_form(fic, pseuds);
+
+        let form = self.get_fic().await?;
+        let form_html = match html {
+            Some(_) => Some(self.text.resolve().await?.html().await?),
+            None => None,
+        };
+        let mismatches = form_mismatches(&expected, &form, form_html.as_deref().zip(html));
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+
+        for change in &mismatches {
+            eprintln_async!(
+                "{}: the form has {}, the spec wants {}",
+                change.field,
+                change.live.as_deref().unwrap_or("nothing"),
+                change.local.as_deref().unwrap_or("nothing")
+            );
+        }
+        bail!(
+            "{} field(s) did not take the spec's value, nothing was submitted",
+            mismatches.len()
+        )
+    }
+
+    pub async fn has_text(&self) -> Result<bool> {
+        let content = self.base.find_all(By::Css("textarea#content")).await?;
+        Ok(!content.is_empty())
+    }
+
+    /// Reads the form back into a spec. Chapters live on their own pages.
+    pub async fn get_fic(&self) -> Result<Fanfiction> {
+        let associations = self.associations.resolve().await?;
+        let mut meta = associations.get_meta().await?;
+        meta.format = FileFormat::HTML;
+
+        Ok(Fanfiction {
+            fic: self.preface.resolve().await?.get_details().await?,
+            tags: self.tags.resolve().await?.get_tags().await?,
+            meta,
+            remix: associations.get_remix().await?,
+            ..Default::default()
+        })
+    }
+
+    pub async fn submit(&self, action: PostAction) -> Result<()> {
+        let buttons = self.submit_buttons.resolve().await?;
+        click_submit(buttons, action, "work").await
     }
 }
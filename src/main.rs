@@ -2,6 +2,8 @@
 #![feature(associated_type_defaults)]
 #![allow(unused)]
 
+use std::process::ExitCode;
+
 use clap::Parser;
 use rootcause::Report;
 
@@ -10,20 +12,24 @@ use crate::command::Ficwright;
 pub mod command;
 pub mod config;
 pub mod driver;
+pub mod exit;
 
 pub mod forms;
+pub mod plan;
+pub mod render;
+pub mod secret;
 pub mod utils;
+pub mod validate;
 
 type Result<X> = std::result::Result<X, Report>;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    tokio::process::Command::new("pkill")
-        .arg("geckodriver")
-        .output()
-        .await?;
-
-    let res = Ficwright::parse().run().await;
-
-    res
+async fn main() -> ExitCode {
+    match Ficwright::parse().run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(report) => {
+            eprintln_async!("Error: {:?}", report);
+            ExitCode::from(exit::exit_code(&report))
+        }
+    }
 }
@@ -0,0 +1,387 @@
+use std::{collections::BTreeSet, fmt};
+
+use indexmap::IndexSet;
+use rootcause::bail;
+use thirtyfour::{By, WebDriver};
+
+use crate::{
+    Result,
+    config::{Fanfiction, FicDetails},
+    driver::{DriverExts, archive},
+    forms::{chapter_form::ChapterForm, entry_key, model::ArchiveWarning, work_form::WorkForm},
+    println_async,
+};
+
+/// A posted work as read back from its edit pages.
+#[derive(Debug, Clone)]
+pub struct LiveWork {
+    pub id: u64,
+    pub fic: Fanfiction,
+    pub chapters: Vec<LiveChapter>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LiveChapter {
+    pub details: FicDetails,
+    pub html: String,
+}
+
+impl LiveWork {
+    pub async fn load(driver: &WebDriver, id: u64) -> Result<Self> {
+        driver.ao3(format!("/works/{id}/edit")).await?;
+
+        let work_form = WorkForm::from(driver.find(By::Id("work-form")).await?);
+        let mut fic = work_form.get_fic().await?;
+        fic.fic.url = format!("{}/works/{id}", archive());
+
+        let paths = driver.chapter_paths(id).await?;
+        if paths.is_empty() {
+            bail!("Work {} has no chapters", id);
+        }
+
+        let mut chapters = vec![];
+        for (i, path) in paths.iter().enumerate() {
+            println_async!("Reading chapter {} of {}", i + 1, paths.len());
+            driver.ao3(format!("{path}/edit")).await?;
+
+            let chapter_form = ChapterForm::from(driver.find(By::Id("chapter-form")).await?);
+            let mut details = chapter_form.get_details().await?;
+            details.url = format!("{}{path}", archive());
+            let html = chapter_form.text.resolve().await?.html().await?;
+
+            chapters.push(LiveChapter { details, html });
+        }
+
+        Ok(Self { id, fic, chapters })
+    }
+}
+
+/// Which page a change is made on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Target {
+    #[default]
+    Work,
+    /// A posted chapter, by its index on the work.
+    Chapter(usize),
+    /// A chapter of the spec that is not posted yet, by its key.
+    NewChapter(String),
+    /// A chapter only on AO3; nothing is done about it.
+    LiveOnly,
+}
+
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub target: Target,
+    pub field: String,
+    pub live: Option<String>,
+    pub local: Option<String>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.live, &self.local) {
+            (Some(live), Some(local)) => write!(f, "~ {}: {} -> {}", self.field, live, local),
+            (None, Some(local)) => write!(f, "+ {}: {}", self.field, local),
+            (Some(live), None) => write!(f, "- {}: {} (left alone)", self.field, live),
+            (None, None) => write!(f, "  {}", self.field),
+        }
+    }
+}
+
+/// Lists every difference between the spec and the live work, field by field.
+/// `pseuds` are the ones the work is posted under.
+pub async fn plan(
+    local: &Fanfiction,
+    pseuds: &IndexSet<String>,
+    live: &LiveWork,
+) -> Result<Vec<Change>> {
+    let mut planner = Planner::default();
+    planner.work_fields(&live.fic, &as_on_form(local, pseuds));
+
+    if local.chapters.is_empty() {
+        // A single chapter work keeps its text on the work form.
+        if let Some(file) = &local.fic.file {
+            let html = local.render_file(file).await?;
+            planner.text("fic.text", &live.chapters[0].html, &html);
+        }
+        for chapter in &live.chapters[1..] {
+            planner.live_only(&chapter.details);
+        }
+        return Ok(planner.changes);
+    }
+
+    let locals: Vec<_> = local.chapters.iter().collect();
+    for (i, (key, chapter)) in locals.iter().enumerate() {
+        let name = |field: &str| format!("chapters.{key}.{field}");
+        let Some(posted) = live.chapters.get(i) else {
+            planner.target = Target::NewChapter(key.to_string());
+            planner.changes.push(Change {
+                target: planner.target.clone(),
+                field: format!("chapters.{key}"),
+                live: None,
+                local: Some(format!("new chapter {:?}", chapter.title)),
+            });
+            continue;
+        };
+
+        planner.target = Target::Chapter(i);
+        let details = &posted.details;
+        planner.field(&name("title"), &details.title, &chapter.title);
+        planner.field(&name("summary"), &details.summary, &chapter.summary);
+        planner.field(
+            &name("start_note"),
+            &details.start_note,
+            &chapter.start_note,
+        );
+        planner.field(&name("end_note"), &details.end_note, &chapter.end_note);
+
+        if let Some(file) = local.chapter_file(i) {
+            let html = local.render_file(file).await?;
+            planner.text(&name("text"), &posted.html, &html);
+        }
+    }
+    for chapter in live.chapters.iter().skip(locals.len()) {
+        planner.live_only(&chapter.details);
+    }
+
+    Ok(planner.changes)
+}
+
+/// The spec as AO3's work form shows it when posted under `pseuds`, with
+/// the defaults AO3 fills in for what the spec leaves out.
+pub fn as_on_form(fic: &Fanfiction, pseuds: &IndexSet<String>) -> Fanfiction {
+    let mut shown = fic.clone();
+    shown.fic.author_pseud = pseuds.clone();
+    if !fic.is_chaptered() {
+        shown.meta.total_chapters = 1;
+    }
+    if shown.tags.warnings.is_empty() {
+        shown.tags.warnings.insert(ArchiveWarning::CNTUAW);
+    }
+    let details = &mut shown.fic;
+    for text in [
+        &mut details.summary,
+        &mut details.start_note,
+        &mut details.end_note,
+    ] {
+        text.take_if(|t| t.is_empty());
+    }
+    shown
+}
+
+/// The fields a filled out work form does not hold the spec's values in;
+/// `html` is the text on the form and the text it should have.
+pub fn form_mismatches(
+    expected: &Fanfiction,
+    form: &Fanfiction,
+    html: Option<(&str, &str)>,
+) -> Vec<Change> {
+    let mut planner = Planner::default();
+    planner.work_fields(form, expected);
+    if let Some((form, expected)) = html {
+        planner.text("fic.text", form, expected);
+    }
+    planner.changes
+}
+
+#[derive(Default)]
+struct Planner {
+    target: Target,
+    changes: Vec<Change>,
+}
+
+impl Planner {
+    /// Compares the work's own fields, leaving its text and chapters out.
+    fn work_fields(&mut self, l: &Fanfiction, r: &Fanfiction) {
+        self.field("fic.title", &l.fic.title, &r.fic.title);
+        self.field("fic.summary", &l.fic.summary, &r.fic.summary);
+        self.field("fic.start_note", &l.fic.start_note, &r.fic.start_note);
+        self.field("fic.end_note", &l.fic.end_note, &r.fic.end_note);
+        if !r.fic.author_pseud.is_empty() {
+            self.field("fic.author_pseud", &l.fic.author_pseud, &r.fic.author_pseud);
+        }
+        self.entries("fic.co_authors", &l.fic.co_authors, &r.fic.co_authors);
+
+        self.field("tags.rating", &l.tags.rating, &r.tags.rating);
+        self.field("tags.warnings", &l.tags.warnings, &r.tags.warnings);
+        self.field("tags.categories", &l.tags.categories, &r.tags.categories);
+        self.entries("tags.fandoms", &l.tags.fandoms, &r.tags.fandoms);
+        self.entries(
+            "tags.relationships",
+            &l.tags.relationships,
+            &r.tags.relationships,
+        );
+        self.entries("tags.characters", &l.tags.characters, &r.tags.characters);
+        self.entries("tags.other", &l.tags.other, &r.tags.other);
+
+        self.field("meta.language", &l.meta.language, &r.meta.language);
+        self.entries("meta.challenges", &l.meta.challenges, &r.meta.challenges);
+        self.entries("meta.gift_to", &l.meta.gift_to, &r.meta.gift_to);
+        self.field("meta.work_skin", &l.meta.work_skin, &r.meta.work_skin);
+        self.field(
+            "meta.total_chapters",
+            &l.meta.total_chapters,
+            &r.meta.total_chapters,
+        );
+        self.field("meta.in_series", &l.meta.in_series, &r.meta.in_series);
+        if r.meta.publication_date.is_some() {
+            self.field(
+                "meta.publication_date",
+                &l.meta.publication_date,
+                &r.meta.publication_date,
+            );
+        }
+
+        let (live_remix, local_remix) = (l.remix.as_ref(), r.remix.as_ref());
+        self.field(
+            "remix.url",
+            &live_remix.map(|x| &x.url),
+            &local_remix.map(|x| &x.url),
+        );
+        self.field(
+            "remix.title",
+            &live_remix.map(|x| &x.title),
+            &local_remix.map(|x| &x.title),
+        );
+        self.field(
+            "remix.author",
+            &live_remix.map(|x| &x.author),
+            &local_remix.map(|x| &x.author),
+        );
+        self.field(
+            "remix.language",
+            &live_remix.map(|x| &x.language),
+            &local_remix.map(|x| &x.language),
+        );
+        self.field(
+            "remix.translated",
+            &live_remix.map(|x| x.translated),
+            &local_remix.map(|x| x.translated),
+        );
+    }
+
+    fn push(&mut self, field: &str, live: Option<String>, local: Option<String>) {
+        self.changes.push(Change {
+            target: self.target.clone(),
+            field: field.to_string(),
+            live,
+            local,
+        });
+    }
+
+    fn field<T: PartialEq + fmt::Debug>(&mut self, field: &str, live: &T, local: &T) {
+        if live != local {
+            self.push(field, Some(format!("{live:?}")), Some(format!("{local:?}")));
+        }
+    }
+
+    /// Autocomplete fields tell entries apart the way AO3 does, ignoring case
+    /// and spacing.
+    fn entries(&mut self, field: &str, live: &IndexSet<String>, local: &IndexSet<String>) {
+        let keys = |set: &IndexSet<String>| -> BTreeSet<String> {
+            set.iter().map(|entry| entry_key(entry)).collect()
+        };
+        if keys(live) != keys(local) {
+            self.push(field, Some(format!("{live:?}")), Some(format!("{local:?}")));
+        }
+    }
+
+    /// AO3 reflows the HTML it is given, so texts are compared with the
+    /// whitespace between tags removed.
+    fn text(&mut self, field: &str, live: &str, local: &str) {
+        let (live, local) = (normalize_html(live), normalize_html(local));
+        if live == local {
+            return;
+        }
+        let at = live
+            .chars()
+            .zip(local.chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        self.push(
+            field,
+            Some(format!("{} characters", live.chars().count())),
+            Some(format!(
+                "{} characters, first difference at character {}",
+                local.chars().count(),
+                at
+            )),
+        );
+    }
+
+    fn live_only(&mut self, chapter: &FicDetails) {
+        self.target = Target::LiveOnly;
+        self.push(
+            &chapter.url,
+            Some(format!("chapter {:?} is not in the spec", chapter.title)),
+            None,
+        );
+    }
+}
+
+pub fn normalize_html(html: &str) -> String {
+    let between = regex::Regex::new(r">\s+<").unwrap();
+    let space = regex::Regex::new(r"\s+").unwrap();
+    let br = regex::Regex::new(r"<br\s*/?>").unwrap();
+    let html = br.replace_all(html.trim(), "<br />");
+    let html = between.replace_all(&html, "><");
+    space.replace_all(&html, " ").into_owned()
+}
+
+#[test]
+fn test_normalize_html() {
+    assert_eq!(
+        normalize_html("<p>One\n  two<br></p>\n\n<p>Three</p>\n"),
+        normalize_html("<p>One two<br /></p><p>Three</p>")
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_plan_defaults() {
+    let mut local = Fanfiction::default();
+    local.fic.title = "One Shot".to_string();
+    local.tags.warnings.clear();
+
+    let mut fic = local.clone();
+    fic.meta.total_chapters = 1;
+    fic.tags.warnings.insert(ArchiveWarning::CNTUAW);
+    let live = LiveWork {
+        id: 1,
+        fic,
+        chapters: vec![LiveChapter {
+            details: FicDetails::default(),
+            html: String::new(),
+        }],
+    };
+    let changes = plan(&local, &IndexSet::new(), &live).await.unwrap();
+    assert!(changes.is_empty(), "{changes:?}");
+}
+
+#[test]
+fn test_form_mismatches() {
+    let mut spec = Fanfiction::default();
+    spec.fic.title = "One Shot".to_string();
+    spec.fic.start_note = Some(String::new());
+    spec.tags.warnings.clear();
+    spec.meta.total_chapters = 0;
+    let pseuds = IndexSet::from(["Main".to_string()]);
+
+    // What AO3's form reads back for that spec.
+    let mut form = spec.clone();
+    form.fic.start_note = None;
+    form.fic.author_pseud = pseuds.clone();
+    form.tags.warnings.insert(ArchiveWarning::CNTUAW);
+    form.meta.total_chapters = 1;
+
+    let expected = as_on_form(&spec, &pseuds);
+    let html = Some(("<p>Text</p>", "<p>Text</p>\n"));
+    assert!(form_mismatches(&expected, &form, html).is_empty());
+
+    form.fic.title = "Other".to_string();
+    let html = Some(("<p>Text</p>", "<p>Txet</p>"));
+    let fields: Vec<_> = form_mismatches(&expected, &form, html)
+        .into_iter()
+        .map(|c| c.field)
+        .collect();
+    assert_eq!(fields, ["fic.title", "fic.text"]);
+}
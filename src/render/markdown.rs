@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+use crate::render::{
+    Diagnostic, check_raw_html, escape_html, footnote_ref, footnotes_section, is_safe_url,
+};
+
+pub fn options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_SMART_PUNCTUATION
+        | Options::ENABLE_DEFINITION_LIST
+        | Options::ENABLE_SUPERSCRIPT
+        | Options::ENABLE_SUBSCRIPT
+}
+
+/// Renders Markdown to the subset of HTML that AO3 accepts.
+pub fn to_html(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let mut writer = Writer {
+        source,
+        ..Default::default()
+    };
+
+    for (event, range) in Parser::new_ext(source, options()).into_offset_iter() {
+        writer.offset = range.start;
+        writer.event(event);
+    }
+
+    writer.finish()
+}
+
+#[derive(Default)]
+struct Writer<'a> {
+    source: &'a str,
+    offset: usize,
+    out: String,
+    diagnostics: Vec<Diagnostic>,
+    note: Option<(String, String)>,
+    notes: HashMap<String, String>,
+    note_order: Vec<String>,
+    image: Option<(String, String, String)>,
+    alignments: Vec<Alignment>,
+    cell: usize,
+    in_head: bool,
+}
+
+impl Writer<'_> {
+    fn push(&mut self, s: &str) {
+        match (&mut self.image, &mut self.note) {
+            (Some((_, _, alt)), _) => alt.push_str(s),
+            (None, Some((_, buf))) => buf.push_str(s),
+            (None, None) => self.out.push_str(s),
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        let diagnostic = Diagnostic::at(self.source, self.offset, message);
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn note_number(&mut self, label: &str) -> usize {
+        match self.note_order.iter().position(|l| l == label) {
+            Some(i) => i + 1,
+            None => {
+                self.note_order.push(label.to_string());
+                self.note_order.len()
+            }
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        if self.image.is_some() {
+            match event {
+                Event::End(TagEnd::Image) => {
+                    let (src, title, alt) = self.image.take().unwrap_or_default();
+                    let mut tag = format!(
+                        "<img src=\"{}\" alt=\"{}\"",
+                        escape_html(&src),
+                        escape_html(&alt)
+                    );
+                    if !title.is_empty() {
+                        tag.push_str(&format!(" title=\"{}\"", escape_html(&title)));
+                    }
+                    tag.push_str(" />");
+                    self.push(&tag);
+                }
+                Event::Text(text) | Event::Code(text) => self.push(&text),
+                _ => {}
+            }
+            return;
+        }
+
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.push(&escape_html(&text)),
+            Event::Code(code) => self.push(&format!("<code>{}</code>", escape_html(&code))),
+            Event::Html(html) | Event::InlineHtml(html) => {
+                for (_, message) in check_raw_html(&html) {
+                    self.error(message);
+                }
+                self.push(&html);
+            }
+            Event::FootnoteReference(label) => {
+                let n = self.note_number(&label);
+                self.push(&footnote_ref(n));
+            }
+            Event::SoftBreak => self.push("\n"),
+            Event::HardBreak => self.push("<br />\n"),
+            Event::Rule => self.push("<hr />\n"),
+            Event::InlineMath(_) | Event::DisplayMath(_) => {
+                self.error("Math is not supported on AO3".to_string())
+            }
+            Event::TaskListMarker(_) => {
+                self.error("Task lists are not supported on AO3".to_string())
+            }
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.push("<p>"),
+            Tag::Heading { level, .. } => self.push(&format!("<{level}>")),
+            Tag::BlockQuote(_) => self.push("<blockquote>\n"),
+            Tag::CodeBlock(CodeBlockKind::Fenced(_) | CodeBlockKind::Indented) => {
+                self.push("<pre><code>")
+            }
+            Tag::HtmlBlock => {}
+            Tag::List(Some(1)) => self.push("<ol>\n"),
+            Tag::List(Some(start)) => self.push(&format!("<ol start=\"{start}\">\n")),
+            Tag::List(None) => self.push("<ul>\n"),
+            Tag::Item => self.push("<li>"),
+            Tag::FootnoteDefinition(label) => {
+                self.note = Some((label.to_string(), String::new()));
+            }
+            Tag::DefinitionList => self.push("<dl>\n"),
+            Tag::DefinitionListTitle => self.push("<dt>"),
+            Tag::DefinitionListDefinition => self.push("<dd>"),
+            Tag::Table(alignments) => {
+                self.alignments = alignments;
+                self.push("<table>\n");
+            }
+            Tag::TableHead => {
+                self.in_head = true;
+                self.cell = 0;
+                self.push("<thead>\n<tr>");
+            }
+            Tag::TableRow => {
+                self.cell = 0;
+                self.push("<tr>");
+            }
+            Tag::TableCell => {
+                let name = if self.in_head { "th" } else { "td" };
+                let align = match self.alignments.get(self.cell) {
+                    Some(Alignment::Left) => " align=\"left\"",
+                    Some(Alignment::Center) => " align=\"center\"",
+                    Some(Alignment::Right) => " align=\"right\"",
+                    _ => "",
+                };
+                self.push(&format!("<{name}{align}>"));
+            }
+            Tag::Emphasis => self.push("<em>"),
+            Tag::Strong => self.push("<strong>"),
+            Tag::Strikethrough => self.push("<del>"),
+            Tag::Superscript => self.push("<sup>"),
+            Tag::Subscript => self.push("<sub>"),
+            Tag::Link {
+                dest_url, title, ..
+            } => {
+                if !is_safe_url(&dest_url) {
+                    self.error(format!("Link target {dest_url:?} is not allowed on AO3"));
+                }
+                let mut tag = format!("<a href=\"{}\"", escape_html(&dest_url));
+                if !title.is_empty() {
+                    tag.push_str(&format!(" title=\"{}\"", escape_html(&title)));
+                }
+                tag.push('>');
+                self.push(&tag);
+            }
+            Tag::Image {
+                dest_url, title, ..
+            } => {
+                if !is_safe_url(&dest_url) {
+                    self.error(format!("Image source {dest_url:?} is not allowed on AO3"));
+                }
+                self.image = Some((dest_url.to_string(), title.to_string(), String::new()));
+            }
+            Tag::MetadataBlock(_) => self.error("Metadata blocks are not supported".to_string()),
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => self.push("</p>\n"),
+            TagEnd::Heading(level) => self.push(&format!("</{level}>\n")),
+            TagEnd::BlockQuote(_) => self.push("</blockquote>\n"),
+            TagEnd::CodeBlock => self.push("</code></pre>\n"),
+            TagEnd::HtmlBlock => {}
+            TagEnd::List(true) => self.push("</ol>\n"),
+            TagEnd::List(false) => self.push("</ul>\n"),
+            TagEnd::Item => self.push("</li>\n"),
+            TagEnd::FootnoteDefinition => {
+                if let Some((label, html)) = self.note.take() {
+                    self.notes.insert(label, html);
+                }
+            }
+            TagEnd::DefinitionList => self.push("</dl>\n"),
+            TagEnd::DefinitionListTitle => self.push("</dt>\n"),
+            TagEnd::DefinitionListDefinition => self.push("</dd>\n"),
+            TagEnd::Table => self.push("</tbody>\n</table>\n"),
+            TagEnd::TableHead => {
+                self.in_head = false;
+                self.push("</tr>\n</thead>\n<tbody>\n");
+            }
+            TagEnd::TableRow => self.push("</tr>\n"),
+            TagEnd::TableCell => {
+                let name = if self.in_head { "th" } else { "td" };
+                self.cell += 1;
+                self.push(&format!("</{name}>"));
+            }
+            TagEnd::Emphasis => self.push("</em>"),
+            TagEnd::Strong => self.push("</strong>"),
+            TagEnd::Strikethrough => self.push("</del>"),
+            TagEnd::Superscript => self.push("</sup>"),
+            TagEnd::Subscript => self.push("</sub>"),
+            TagEnd::Link => self.push("</a>"),
+            TagEnd::Image | TagEnd::MetadataBlock(_) => {}
+        }
+    }
+
+    fn finish(mut self) -> Result<String, Vec<Diagnostic>> {
+        if !self.diagnostics.is_empty() {
+            return Err(self.diagnostics);
+        }
+
+        let notes: Vec<String> = self
+            .note_order
+            .iter()
+            .map(|label| self.notes.get(label).cloned().unwrap_or_default())
+            .collect();
+        self.out.push_str(&footnotes_section(&notes));
+
+        Ok(self.out)
+    }
+}
+
+#[test]
+fn test_markdown_to_html() {
+    let html = to_html("# Title\n\n*Hello*, **world**[^1].\n\n[^1]: A note.\n").unwrap();
+    assert_eq!(
+        html,
+        "<h1>Title</h1>\n\
+         <p><em>Hello</em>, <strong>world</strong>\
+         <sup><a href=\"#footnote1\" name=\"footnote-ref1\">1</a></sup>.</p>\n\
+         <div class=\"footnotes\">\n<hr />\n<ol>\n\
+         <li><a name=\"footnote1\"></a><p>A note.</p> <a href=\"#footnote-ref1\">↩</a></li>\n\
+         </ol>\n</div>\n"
+    );
+
+    let errs = to_html("Fine.\n\n<script>alert(1)</script>\n").unwrap_err();
+    assert_eq!(errs[0].line, 3);
+}
@@ -0,0 +1,316 @@
+use std::fmt;
+
+use rootcause::{Report, report};
+
+pub mod markdown;
+pub mod typst;
+
+/// Tags that survive AO3's HTML sanitizer.
+pub const AO3_TAGS: &[&str] = &[
+    "a",
+    "abbr",
+    "acronym",
+    "address",
+    "b",
+    "big",
+    "blockquote",
+    "br",
+    "caption",
+    "center",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "del",
+    "details",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "rp",
+    "rt",
+    "ruby",
+    "s",
+    "samp",
+    "small",
+    "span",
+    "strike",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "tt",
+    "u",
+    "ul",
+    "var",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn at(source: &str, offset: usize, message: impl Into<String>) -> Self {
+        let before = &source[..offset.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rsplit('\n')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .count()
+            + 1;
+        Diagnostic {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+pub fn diagnostics_report(diagnostics: &[Diagnostic]) -> Report {
+    let mut msg = String::from("Cannot render text for AO3:");
+    for d in diagnostics {
+        msg.push_str(&format!("\n  {d}"));
+    }
+    report!("{}", msg)
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// AO3 strips `id` attributes, so footnotes link up through named anchors.
+pub fn footnote_ref(n: usize) -> String {
+    format!("<sup><a href=\"#footnote{n}\" name=\"footnote-ref{n}\">{n}</a></sup>")
+}
+
+/// Decodes the entities a browser leaves in `inner_html`, named or numeric.
+pub fn unescape_html(html: &str) -> String {
+    let entity = regex::Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap();
+    entity
+        .replace_all(html, |cap: &regex::Captures| {
+            let name = &cap[1];
+            let decoded = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => name
+                    .strip_prefix("#x")
+                    .or_else(|| name.strip_prefix("#X"))
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| name.strip_prefix('#').map(str::parse))
+                    .and_then(|n| n.ok())
+                    .and_then(char::from_u32),
+            };
+            decoded.map_or_else(|| cap[0].to_string(), String::from)
+        })
+        .into_owned()
+}
+
+pub fn footnotes_section(notes: &[String]) -> String {
+    if notes.is_empty() {
+        return String::new();
+    }
+    let mut res = String::from("<div class=\"footnotes\">\n<hr />\n<ol>\n");
+    for (i, html) in notes.iter().enumerate() {
+        let n = i + 1;
+        res.push_str(&format!(
+            "<li><a name=\"footnote{n}\"></a>{} <a href=\"#footnote-ref{n}\">↩</a></li>\n",
+            html.trim_end()
+        ));
+    }
+    res.push_str("</ol>\n</div>\n");
+    res
+}
+
+/// Attributes AO3 keeps on any allowed tag.
+pub const AO3_COMMON_ATTRIBUTES: &[&str] = &["align", "class", "dir", "title"];
+
+/// Attributes AO3 keeps on particular tags, besides the common ones.
+pub const AO3_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("a", &["href", "name"]),
+    ("blockquote", &["cite"]),
+    ("col", &["span", "width"]),
+    ("colgroup", &["span", "width"]),
+    ("del", &["cite", "datetime"]),
+    ("details", &["open"]),
+    ("hr", &["width"]),
+    ("img", &["alt", "border", "height", "src", "width"]),
+    ("ins", &["cite", "datetime"]),
+    ("ol", &["start", "type"]),
+    ("q", &["cite"]),
+    ("table", &["border", "summary", "width"]),
+    (
+        "td",
+        &["abbr", "axis", "colspan", "height", "rowspan", "width"],
+    ),
+    (
+        "th",
+        &[
+            "abbr", "axis", "colspan", "height", "rowspan", "scope", "width",
+        ],
+    ),
+    ("ul", &["type"]),
+];
+
+fn allows_attribute(tag: &str, attr: &str) -> bool {
+    AO3_COMMON_ATTRIBUTES.contains(&attr)
+        || AO3_ATTRIBUTES
+            .iter()
+            .any(|(t, attrs)| *t == tag && attrs.contains(&attr))
+}
+
+/// Finds every tag in a snippet of raw HTML that is not in [`AO3_TAGS`],
+/// and every attribute AO3 would strip, each with its offset.
+pub fn check_raw_html(html: &str) -> Vec<(usize, String)> {
+    let tag_re =
+        regex::Regex::new(r#"<\s*(/?)\s*([a-zA-Z][a-zA-Z0-9]*)((?:[^>"']|"[^"]*"|'[^']*')*)"#)
+            .unwrap();
+    let attr_re =
+        regex::Regex::new(r#"([^\s"'>/=]+)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+)))?"#)
+            .unwrap();
+    let mut res = vec![];
+    for cap in tag_re.captures_iter(html) {
+        let at = cap.get(0).map(|m| m.start()).unwrap_or_default();
+        let tag = cap[2].to_lowercase();
+        if !AO3_TAGS.contains(&tag.as_str()) {
+            res.push((
+                at,
+                format!("<{tag}> is not allowed by AO3's HTML sanitizer"),
+            ));
+            continue;
+        }
+        if !cap[1].is_empty() {
+            continue;
+        }
+        for attr in attr_re.captures_iter(&cap[3]) {
+            let name = attr[1].to_lowercase();
+            let value = attr.get(2).or(attr.get(3)).or(attr.get(4));
+            if !allows_attribute(&tag, &name) {
+                res.push((
+                    at,
+                    format!(
+                        "The {name} attribute of <{tag}> is not allowed by AO3's HTML sanitizer"
+                    ),
+                ));
+            } else if ["href", "src", "cite"].contains(&name.as_str())
+                && let Some(url) = value
+                && !is_safe_url(&unescape_html(url.as_str()))
+            {
+                res.push((
+                    at,
+                    format!("Unsafe link in the {name} attribute of <{tag}>"),
+                ));
+            }
+        }
+    }
+    res
+}
+
+pub fn check_html(html: &str) -> Result<(), Vec<Diagnostic>> {
+    let diagnostics: Vec<Diagnostic> = check_raw_html(html)
+        .into_iter()
+        .map(|(offset, message)| Diagnostic::at(html, offset, message))
+        .collect();
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// Links AO3 would strip or that lead nowhere once posted.
+pub fn is_safe_url(url: &str) -> bool {
+    let lower = url.trim().to_lowercase();
+    !(lower.starts_with("javascript:")
+        || lower.starts_with("data:")
+        || lower.starts_with("vbscript:"))
+}
+
+#[test]
+fn test_unescape_html() {
+    assert_eq!(
+        unescape_html("Bleach (Anime &amp; Manga)"),
+        "Bleach (Anime & Manga)"
+    );
+    assert_eq!(unescape_html("&#39;&#x2F;&bogus;"), "'/&bogus;");
+    assert_eq!(unescape_html(&escape_html("<a & \"b\">")), "<a & \"b\">");
+}
+
+#[test]
+fn test_check_raw_html() {
+    assert!(check_raw_html("<p align=\"center\" class='x'>Hi<br/></p>").is_empty());
+    assert!(check_raw_html("<a href=\"https://example.org\" name=top>x</a>").is_empty());
+
+    let messages =
+        |html| -> Vec<String> { check_raw_html(html).into_iter().map(|(_, m)| m).collect() };
+    assert_eq!(
+        messages("<span style=\"color: red\" onclick=\"x()\" id=a>x</span>"),
+        [
+            "The style attribute of <span> is not allowed by AO3's HTML sanitizer",
+            "The onclick attribute of <span> is not allowed by AO3's HTML sanitizer",
+            "The id attribute of <span> is not allowed by AO3's HTML sanitizer",
+        ]
+    );
+    assert_eq!(
+        messages("<p title=\"a > b\"><script>x</script></p>"),
+        [
+            "<script> is not allowed by AO3's HTML sanitizer",
+            "<script> is not allowed by AO3's HTML sanitizer",
+        ]
+    );
+    assert_eq!(
+        messages("<a href=\"javascript:alert(1)\">x</a>"),
+        ["Unsafe link in the href attribute of <a>"]
+    );
+}
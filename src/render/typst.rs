@@ -0,0 +1,702 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+use typst_syntax::{
+    DiagSpanKind, Source, Span,
+    ast::{self, AstNode, Expr},
+};
+
+use crate::render::{Diagnostic, escape_html, footnote_ref, footnotes_section, is_safe_url};
+
+/// Renders Typst markup to the subset of HTML that AO3 accepts.
+///
+/// Only markup is converted; scripting is limited to content bindings with
+/// `#let` and show rules whose transformation is an element function, content
+/// or `none`. Set rules only concern page layout and are ignored.
+pub fn to_html(text: &str) -> Result<String, Vec<Diagnostic>> {
+    let source = Source::detached(text);
+    let mut writer = Writer {
+        source: &source,
+        diagnostics: vec![],
+        notes: vec![],
+        bindings: HashMap::new(),
+        element_rules: HashMap::new(),
+        text_rules: vec![],
+    };
+
+    writer.syntax_errors();
+
+    let mut out = match source.root().cast::<ast::Markup>() {
+        Some(markup) => writer.blocks(markup),
+        None => String::new(),
+    };
+
+    if !writer.diagnostics.is_empty() {
+        return Err(writer.diagnostics);
+    }
+
+    out.push_str(&footnotes_section(&writer.notes));
+    Ok(out)
+}
+
+#[derive(Debug, Clone)]
+enum Transform {
+    Wrap(&'static str),
+    Replace(String),
+    Hide,
+}
+
+impl Transform {
+    fn apply(&self, html: String) -> String {
+        match self {
+            Self::Wrap(tag) => format!("<{tag}>{html}</{tag}>"),
+            Self::Replace(replacement) => replacement.clone(),
+            Self::Hide => String::new(),
+        }
+    }
+}
+
+/// Elements a show rule can select, and what they render as.
+const ELEMENTS: &[(&str, &str)] = &[
+    ("strong", "strong"),
+    ("emph", "em"),
+    ("underline", "u"),
+    ("strike", "del"),
+    ("sub", "sub"),
+    ("super", "sup"),
+    ("quote", "q"),
+    ("raw", "code"),
+    ("link", "a"),
+    ("heading", "h1"),
+    ("footnote", "sup"),
+];
+
+fn element_tag(name: &str) -> Option<&'static str> {
+    ELEMENTS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, tag)| *tag)
+}
+
+/// Inline content being built up, with the text since the last element kept
+/// apart so that text show rules can match across words.
+#[derive(Default)]
+struct Run {
+    html: String,
+    text: String,
+}
+
+impl Run {
+    fn last_char(&self) -> Option<char> {
+        self.text
+            .chars()
+            .last()
+            .or_else(|| self.html.chars().last())
+    }
+}
+
+struct Writer<'s> {
+    source: &'s Source,
+    diagnostics: Vec<Diagnostic>,
+    notes: Vec<String>,
+    bindings: HashMap<String, String>,
+    element_rules: HashMap<&'static str, Vec<Transform>>,
+    text_rules: Vec<(Regex, Transform)>,
+}
+
+impl Writer<'_> {
+    fn error(&mut self, span: Span, message: impl Into<String>) {
+        let offset = self.source.find(span).map(|n| n.offset()).unwrap_or(0);
+        let diagnostic = Diagnostic::at(self.source.text(), offset, message);
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn syntax_errors(&mut self) {
+        let (errors, _) = self.source.root().errors_and_warnings();
+        for err in errors {
+            let offset = match err.span.get() {
+                DiagSpanKind::Number { num, sub_range, .. } => self
+                    .source
+                    .range(num, sub_range)
+                    .map(|r| r.start)
+                    .unwrap_or(0),
+                DiagSpanKind::Range { range, .. } => range.start,
+                DiagSpanKind::Detached => 0,
+            };
+            let diagnostic = Diagnostic::at(self.source.text(), offset, err.message.as_str());
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn element(&self, name: &str, html: String) -> String {
+        let mut html = html;
+        if let Some(rules) = self.element_rules.get(name) {
+            for rule in rules {
+                html = rule.apply(html);
+            }
+        }
+        html
+    }
+
+    fn flush_text(&self, run: &mut Run) {
+        if run.text.is_empty() {
+            return;
+        }
+        let text = std::mem::take(&mut run.text);
+
+        // Split the text into plain stretches and the HTML of matched stretches.
+        let mut pieces = vec![(text, false)];
+        for (re, transform) in &self.text_rules {
+            let mut next = vec![];
+            for (piece, done) in pieces {
+                if done {
+                    next.push((piece, done));
+                    continue;
+                }
+                let mut last = 0;
+                for m in re.find_iter(&piece) {
+                    if m.start() == m.end() {
+                        continue;
+                    }
+                    next.push((piece[last..m.start()].to_string(), false));
+                    next.push((transform.apply(escape_html(m.as_str())), true));
+                    last = m.end();
+                }
+                next.push((piece[last..].to_string(), false));
+            }
+            pieces = next;
+        }
+
+        for (piece, done) in pieces {
+            if done {
+                run.html.push_str(&piece);
+            } else {
+                run.html.push_str(&escape_html(&piece));
+            }
+        }
+    }
+
+    fn finish_run(&self, mut run: Run) -> String {
+        self.flush_text(&mut run);
+        run.html
+    }
+
+    /// The show rules in force before `render` runs, and again after it: a
+    /// rule lasts until the end of the markup it is written in.
+    fn scoped(&mut self, render: impl FnOnce(&mut Self) -> String) -> String {
+        let element_rules = self.element_rules.clone();
+        let text_rules = self.text_rules.clone();
+        let html = render(self);
+        self.element_rules = element_rules;
+        self.text_rules = text_rules;
+        html
+    }
+
+    fn blocks(&mut self, markup: ast::Markup) -> String {
+        self.scoped(|this| this.blocks_in_scope(markup))
+    }
+
+    fn blocks_in_scope(&mut self, markup: ast::Markup) -> String {
+        let mut out = String::new();
+        let mut para = Run::default();
+        let mut list: Option<&'static str> = None;
+
+        for expr in markup.exprs() {
+            let block = match expr {
+                Expr::Parbreak(_) => {
+                    self.end_paragraph(&mut out, &mut para);
+                    Self::end_list(&mut out, &mut list);
+                    continue;
+                }
+                Expr::Space(_) if list.is_some() => continue,
+                Expr::Heading(heading) => {
+                    let depth = heading.depth().get().min(6);
+                    let body = self.inline(heading.body());
+                    let html = self.element("heading", body);
+                    Some(format!("<h{depth}>{html}</h{depth}>\n"))
+                }
+                Expr::Raw(raw) if raw.block() => {
+                    let lines: Vec<String> = raw.lines().map(|l| escape_html(l.get())).collect();
+                    let html = self.element("raw", lines.join("\n"));
+                    Some(format!("<pre><code>{html}</code></pre>\n"))
+                }
+                Expr::FuncCall(call) => self.block_call(call),
+                _ => None,
+            };
+
+            if let Some(block) = block {
+                self.end_paragraph(&mut out, &mut para);
+                Self::end_list(&mut out, &mut list);
+                out.push_str(&block);
+                continue;
+            }
+
+            let item = match expr {
+                Expr::ListItem(item) => Some(("ul", None, self.item(item.body()))),
+                Expr::EnumItem(item) => {
+                    let start = item.number().filter(|n| *n != 1);
+                    Some(("ol", start, self.item(item.body())))
+                }
+                Expr::TermItem(item) => {
+                    let term = self.inline(item.term());
+                    let desc = self.item(item.description());
+                    Some(("dl", None, format!("<dt>{term}</dt>\n<dd>{desc}</dd>\n")))
+                }
+                _ => None,
+            };
+
+            match item {
+                Some((kind, start, html)) => {
+                    self.end_paragraph(&mut out, &mut para);
+                    if list != Some(kind) {
+                        Self::end_list(&mut out, &mut list);
+                        match start {
+                            Some(n) => out.push_str(&format!("<{kind} start=\"{n}\">\n")),
+                            None => out.push_str(&format!("<{kind}>\n")),
+                        }
+                        list = Some(kind);
+                    }
+                    if kind == "dl" {
+                        out.push_str(&html);
+                    } else {
+                        out.push_str(&format!("<li>{html}</li>\n"));
+                    }
+                }
+                None => {
+                    Self::end_list(&mut out, &mut list);
+                    self.inline_expr(expr, &mut para);
+                }
+            }
+        }
+
+        self.end_paragraph(&mut out, &mut para);
+        Self::end_list(&mut out, &mut list);
+        out
+    }
+
+    fn end_paragraph(&self, out: &mut String, para: &mut Run) {
+        let html = self.finish_run(std::mem::take(para));
+        let html = html.trim();
+        if !html.is_empty() {
+            out.push_str(&format!("<p>{html}</p>\n"));
+        }
+    }
+
+    fn end_list(out: &mut String, list: &mut Option<&'static str>) {
+        if let Some(kind) = list.take() {
+            out.push_str(&format!("</{kind}>\n"));
+        }
+    }
+
+    /// List items are inline unless they contain paragraph breaks.
+    fn item(&mut self, body: ast::Markup) -> String {
+        if body.exprs().any(|e| matches!(e, Expr::Parbreak(_))) {
+            self.blocks(body).trim_end().to_string()
+        } else {
+            self.inline(body).trim().to_string()
+        }
+    }
+
+    fn inline(&mut self, markup: ast::Markup) -> String {
+        self.scoped(|this| {
+            let mut run = Run::default();
+            for expr in markup.exprs() {
+                this.inline_expr(expr, &mut run);
+            }
+            this.finish_run(run)
+        })
+    }
+
+    fn push_html(&self, run: &mut Run, html: &str) {
+        self.flush_text(run);
+        run.html.push_str(html);
+    }
+
+    fn inline_expr(&mut self, expr: Expr, run: &mut Run) {
+        match expr {
+            Expr::Text(text) => run.text.push_str(text.get()),
+            Expr::Space(_) => run.text.push(' '),
+            Expr::Escape(escape) => run.text.push(escape.get()),
+            Expr::Shorthand(shorthand) => run.text.push(shorthand.get()),
+            Expr::SmartQuote(quote) => {
+                let opening = match run.last_char() {
+                    None => true,
+                    Some(c) => c.is_whitespace() || "([{“‘>".contains(c),
+                };
+                let c = match (quote.double(), opening) {
+                    (true, true) => '“',
+                    (true, false) => '”',
+                    (false, true) => '‘',
+                    (false, false) => '’',
+                };
+                run.text.push(c);
+            }
+            Expr::Linebreak(_) => self.push_html(run, "<br />\n"),
+            Expr::Parbreak(_) => self.push_html(run, "<br />\n<br />\n"),
+            Expr::Strong(strong) => {
+                let body = self.inline(strong.body());
+                let html = self.element("strong", format!("<strong>{body}</strong>"));
+                self.push_html(run, &html);
+            }
+            Expr::Emph(emph) => {
+                let body = self.inline(emph.body());
+                let html = self.element("emph", format!("<em>{body}</em>"));
+                self.push_html(run, &html);
+            }
+            Expr::Raw(raw) => {
+                let lines: Vec<String> = raw.lines().map(|l| escape_html(l.get())).collect();
+                let html = self.element("raw", format!("<code>{}</code>", lines.join(" ")));
+                self.push_html(run, &html);
+            }
+            Expr::Link(link) => {
+                let url = escape_html(link.get());
+                let html = self.element("link", format!("<a href=\"{url}\">{url}</a>"));
+                self.push_html(run, &html);
+            }
+            Expr::Label(_) => {}
+            Expr::ContentBlock(block) => {
+                let html = self.inline(block.body());
+                self.push_html(run, &html);
+            }
+            Expr::Str(s) => run.text.push_str(&s.get()),
+            Expr::Ident(ident) => match self.bindings.get(ident.as_str()) {
+                Some(html) => {
+                    let html = html.clone();
+                    self.push_html(run, &html);
+                }
+                None => self.error(
+                    ident.span(),
+                    format!("Unknown variable `{}`", ident.as_str()),
+                ),
+            },
+            Expr::FuncCall(call) => {
+                if let Some(html) = self.inline_call(call) {
+                    self.push_html(run, &html);
+                }
+            }
+            Expr::LetBinding(binding) => self.let_binding(binding),
+            Expr::ShowRule(rule) => {
+                // A show rule only changes what comes after it.
+                self.flush_text(run);
+                self.show_rule(rule);
+            }
+            // Set rules style the page, which AO3 does for us.
+            Expr::SetRule(_) => {}
+            Expr::Ref(r) => self.error(
+                r.span(),
+                "References have no AO3 equivalent; use a link instead",
+            ),
+            Expr::Equation(eq) => self.error(eq.span(), "Equations are not supported on AO3"),
+            Expr::Heading(_) | Expr::ListItem(_) | Expr::EnumItem(_) | Expr::TermItem(_) => self
+                .error(
+                    expr.span(),
+                    "Headings and list items must start on their own line here",
+                ),
+            other => self.error(
+                other.span(),
+                format!(
+                    "`{}` is not supported when converting to AO3 HTML",
+                    other.to_untyped().kind().name()
+                ),
+            ),
+        }
+    }
+
+    fn callee(&mut self, call: ast::FuncCall) -> Option<&'static str> {
+        const FUNCTIONS: &[&str] = &[
+            "strong",
+            "emph",
+            "underline",
+            "strike",
+            "sub",
+            "super",
+            "footnote",
+            "link",
+            "quote",
+            "linebreak",
+            "parbreak",
+            "align",
+            "pagebreak",
+            "line",
+            "h",
+            "v",
+            "text",
+        ];
+        match call.callee() {
+            Expr::Ident(ident) => match FUNCTIONS.iter().find(|f| **f == ident.as_str()) {
+                Some(f) => Some(f),
+                None => {
+                    self.error(
+                        ident.span(),
+                        format!("`#{}` has no AO3 equivalent", ident.as_str()),
+                    );
+                    None
+                }
+            },
+            other => {
+                self.error(
+                    other.span(),
+                    "Only calls to built-in functions are supported",
+                );
+                None
+            }
+        }
+    }
+
+    /// The content argument of a call, rendered inline.
+    fn body_arg(&mut self, call: ast::FuncCall) -> String {
+        for arg in call.args().items() {
+            if let ast::Arg::Pos(expr @ (Expr::ContentBlock(_) | Expr::Str(_))) = arg {
+                let mut run = Run::default();
+                self.inline_expr(expr, &mut run);
+                return self.finish_run(run);
+            }
+        }
+        String::new()
+    }
+
+    fn named_arg<'a>(call: ast::FuncCall<'a>, name: &str) -> Option<Expr<'a>> {
+        call.args().items().find_map(|arg| match arg {
+            ast::Arg::Named(named) if named.name().as_str() == name => Some(named.expr()),
+            _ => None,
+        })
+    }
+
+    fn block_call(&mut self, call: ast::FuncCall) -> Option<String> {
+        let Expr::Ident(ident) = call.callee() else {
+            return None;
+        };
+        match ident.as_str() {
+            "quote" => {
+                if !matches!(Self::named_arg(call, "block"), Some(Expr::Bool(b)) if b.get()) {
+                    return None;
+                }
+                let body = call.args().items().find_map(|arg| match arg {
+                    ast::Arg::Pos(Expr::ContentBlock(block)) => Some(block.body()),
+                    _ => None,
+                });
+                let mut html = match body {
+                    Some(body) => self.blocks(body),
+                    None => String::new(),
+                };
+                if let Some(attribution) = Self::named_arg(call, "attribution") {
+                    let mut run = Run::default();
+                    self.inline_expr(attribution, &mut run);
+                    html.push_str(&format!("<p>— {}</p>\n", self.finish_run(run)));
+                }
+                let html = self.element("quote", html);
+                Some(format!("<blockquote>\n{html}</blockquote>\n"))
+            }
+            "align" => {
+                let mut align = None;
+                let mut body = None;
+                for arg in call.args().items() {
+                    match arg {
+                        ast::Arg::Pos(Expr::Ident(a)) => align = Some(a.as_str()),
+                        ast::Arg::Pos(Expr::ContentBlock(b)) => body = Some(b.body()),
+                        _ => {}
+                    }
+                }
+                let align = match align {
+                    Some(a @ ("left" | "center" | "right")) => a,
+                    Some("start") => "left",
+                    Some("end") => "right",
+                    _ => {
+                        self.error(
+                            call.span(),
+                            "Only left, center and right alignment is supported",
+                        );
+                        return Some(String::new());
+                    }
+                };
+                let html = match body {
+                    Some(body) => self.blocks(body),
+                    None => String::new(),
+                };
+                Some(format!("<div align=\"{align}\">\n{html}</div>\n"))
+            }
+            "pagebreak" | "line" => Some("<hr />\n".to_string()),
+            _ => None,
+        }
+    }
+
+    fn inline_call(&mut self, call: ast::FuncCall) -> Option<String> {
+        let name = self.callee(call)?;
+        let html = match name {
+            "strong" | "emph" | "underline" | "strike" | "sub" | "super" => {
+                let tag = element_tag(name).unwrap_or("span");
+                let body = self.body_arg(call);
+                self.element(name, format!("<{tag}>{body}</{tag}>"))
+            }
+            "footnote" => {
+                let body = self.body_arg(call);
+                self.notes.push(format!("<p>{body}</p>"));
+                self.element("footnote", footnote_ref(self.notes.len()))
+            }
+            "link" => {
+                let url = call.args().items().find_map(|arg| match arg {
+                    ast::Arg::Pos(Expr::Str(s)) => Some(s.get().to_string()),
+                    _ => None,
+                });
+                let Some(url) = url else {
+                    self.error(call.span(), "`#link` needs a destination");
+                    return None;
+                };
+                if !is_safe_url(&url) {
+                    self.error(
+                        call.span(),
+                        format!("Link target {url:?} is not allowed on AO3"),
+                    );
+                }
+                let body = call.args().items().find_map(|arg| match arg {
+                    ast::Arg::Pos(Expr::ContentBlock(block)) => Some(block.body()),
+                    _ => None,
+                });
+                let body = match body {
+                    Some(body) => self.inline(body),
+                    None => escape_html(&url),
+                };
+                let url = escape_html(&url);
+                self.element("link", format!("<a href=\"{url}\">{body}</a>"))
+            }
+            "quote" => {
+                let body = self.body_arg(call);
+                self.element("quote", format!("<q>{body}</q>"))
+            }
+            "linebreak" => "<br />\n".to_string(),
+            "parbreak" => "<br />\n<br />\n".to_string(),
+            // Text styling and spacing is left to the reader's skin.
+            "text" => self.body_arg(call),
+            "h" | "v" => String::new(),
+            _ => {
+                self.error(call.span(), format!("`#{name}` must stand on its own line"));
+                return None;
+            }
+        };
+        Some(html)
+    }
+
+    fn let_binding(&mut self, binding: ast::LetBinding) {
+        let name = match binding.kind() {
+            ast::LetBindingKind::Normal(ast::Pattern::Normal(Expr::Ident(ident))) => {
+                ident.as_str().to_string()
+            }
+            _ => {
+                self.error(binding.span(), "Only `#let name = [content]` is supported");
+                return;
+            }
+        };
+        let html = match binding.init() {
+            Some(Expr::ContentBlock(block)) => self.inline(block.body()),
+            Some(Expr::Str(s)) => escape_html(&s.get()),
+            _ => {
+                self.error(
+                    binding.span(),
+                    "Only content and strings can be bound with `#let`",
+                );
+                return;
+            }
+        };
+        self.bindings.insert(name, html);
+    }
+
+    fn show_rule(&mut self, rule: ast::ShowRule) {
+        let transform = match rule.transform() {
+            Expr::Ident(ident) => match element_tag(ident.as_str()) {
+                Some(tag) if !matches!(ident.as_str(), "heading" | "footnote" | "link" | "raw") => {
+                    Transform::Wrap(tag)
+                }
+                _ => {
+                    self.error(
+                        ident.span(),
+                        format!(
+                            "`{}` cannot be used to transform a show rule",
+                            ident.as_str()
+                        ),
+                    );
+                    return;
+                }
+            },
+            Expr::ContentBlock(block) => Transform::Replace(self.inline(block.body())),
+            Expr::Str(s) => Transform::Replace(escape_html(&s.get())),
+            Expr::None(_) => Transform::Hide,
+            // Styling the selected element is a no-op, like any set rule.
+            Expr::SetRule(_) => return,
+            other => {
+                self.error(
+                    other.span(),
+                    "Show rules can only transform with an element function, content or `none`",
+                );
+                return;
+            }
+        };
+
+        match rule.selector() {
+            Some(Expr::Str(s)) => {
+                let re = Regex::new(&regex::escape(&s.get())).unwrap();
+                self.text_rules.push((re, transform));
+            }
+            Some(Expr::FuncCall(call)) if matches!(call.callee(), Expr::Ident(i) if i.as_str() == "regex") =>
+            {
+                let pattern = call.args().items().find_map(|arg| match arg {
+                    ast::Arg::Pos(Expr::Str(s)) => Some(s.get()),
+                    _ => None,
+                });
+                match pattern.map(|p| Regex::new(&p)) {
+                    Some(Ok(re)) => self.text_rules.push((re, transform)),
+                    Some(Err(e)) => self.error(call.span(), format!("Invalid regex: {e}")),
+                    None => self.error(call.span(), "`regex` needs a pattern"),
+                }
+            }
+            Some(Expr::Ident(ident)) => match ELEMENTS.iter().find(|(n, _)| *n == ident.as_str()) {
+                Some((name, _)) => self.element_rules.entry(name).or_default().push(transform),
+                None => self.error(
+                    ident.span(),
+                    format!("Cannot select `{}` in a show rule", ident.as_str()),
+                ),
+            },
+            Some(other) => self.error(
+                other.span(),
+                "Show rules can only select text, a regex or an element",
+            ),
+            None => self.error(
+                rule.span(),
+                "Show rules applying to the whole document are not supported",
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_typst_to_html() {
+    let html = to_html(
+        "#show \"Megumi\": strong\n\
+         = Title\n\n\
+         _Hello_, Megumi#footnote[A note.].\n\n\
+         - one\n\
+         - two\n",
+    )
+    .unwrap();
+    assert_eq!(
+        html,
+        "<h1>Title</h1>\n\
+         <p><em>Hello</em>, <strong>Megumi</strong><sup><a href=\"#footnote1\" \
+         name=\"footnote-ref1\">1</a></sup>.</p>\n\
+         <ul>\n<li>one</li>\n<li>two</li>\n</ul>\n\
+         <div class=\"footnotes\">\n<hr />\n<ol>\n\
+         <li><a name=\"footnote1\"></a><p>A note.</p> <a href=\"#footnote-ref1\">↩</a></li>\n\
+         </ol>\n</div>\n"
+    );
+
+    let errs = to_html("Fine.\n\n#image(\"cover.png\")\n").unwrap_err();
+    assert_eq!((errs[0].line, errs[0].column), (3, 2));
+}
+
+#[test]
+fn test_show_rule_scope() {
+    let html = to_html("Megumi, #show \"Megumi\": strong\nMegumi.\n").unwrap();
+    assert_eq!(html, "<p>Megumi, <strong>Megumi</strong>.</p>\n");
+
+    let html = to_html("#[#show \"x\": \"y\"\nx] x\n\nx\n").unwrap();
+    assert_eq!(html, "<p>y x</p>\n<p>x</p>\n");
+}
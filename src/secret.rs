@@ -0,0 +1,227 @@
+//! Encryption and permissions for the cookie jar, which holds a logged in
+//! AO3 session.
+
+use std::{fmt, os::unix::fs::PermissionsExt, path::Path, process::Stdio};
+
+use argon2::Argon2;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chacha20poly1305::{
+    ChaCha20Poly1305, KeyInit, Nonce,
+    aead::{Aead, OsRng, rand_core::RngCore},
+};
+use rootcause::{bail, report};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::{Result, exit::Failure};
+
+/// The first line of an encrypted cookie file.
+pub const HEADER: &str = "ficwright encrypted cookies v1";
+
+/// Set to use a passphrase without being asked for it.
+pub const PASSPHRASE_VAR: &str = "FICWRIGHT_COOKIE_PASSPHRASE";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Where the key that encrypts the cookie jar comes from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CookieKey {
+    /// Ask for a passphrase, unless `FICWRIGHT_COOKIE_PASSPHRASE` is set.
+    Passphrase,
+    /// The first line a command prints, e.g. `pass show ao3/ficwright`.
+    Command(String),
+}
+
+impl CookieKey {
+    pub async fn secret(&self, interactive: bool) -> Result<Secret> {
+        let secret = match self {
+            Self::Passphrase => {
+                if let Ok(passphrase) = std::env::var(PASSPHRASE_VAR) {
+                    passphrase
+                } else if interactive {
+                    tokio::task::spawn_blocking(|| {
+                        rpassword::prompt_password("Cookie passphrase: ")
+                    })
+                    .await??
+                } else {
+                    return Err(Failure::NeedsInteraction.report(format!(
+                        "The cookie passphrase is needed, set {PASSPHRASE_VAR}"
+                    )));
+                }
+            }
+            Self::Command(command) => run_command(command)
+                .await?
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        };
+        if secret.is_empty() {
+            bail!("The cookie key is empty");
+        }
+        Ok(Secret(secret))
+    }
+}
+
+/// What a credential command such as `pass ao3` prints: the password on
+/// the first line, and optionally a `login:`, `username:` or `user:` line.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub username: String,
+    pub password: Secret,
+}
+
+impl Credentials {
+    /// Runs `command`; `username` is used when its output names none.
+    pub async fn from_command(command: &str, username: Option<&str>) -> Result<Self> {
+        let output = run_command(command).await?;
+        let Some(credentials) = Self::parse(&output, username) else {
+            bail!(
+                "Credential command {:?} printed no password, or no user name to go with it",
+                command
+            );
+        };
+        Ok(credentials)
+    }
+
+    fn parse(output: &str, username: Option<&str>) -> Option<Self> {
+        let mut lines = output.lines();
+        let password = lines.next().filter(|p| !p.is_empty())?;
+        let named = lines.find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            ["login", "username", "user"]
+                .contains(&key.trim().to_lowercase().as_str())
+                .then(|| value.trim())
+        });
+        Some(Self {
+            username: username.or(named)?.to_string(),
+            password: Secret(password.to_string()),
+        })
+    }
+}
+
+async fn run_command(command: &str) -> Result<String> {
+    let output = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!("Command {:?} failed with {}", command, output.status);
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// A passphrase or key, kept out of debug output.
+#[derive(Clone)]
+pub struct Secret(String);
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    fn cipher(&self, salt: &[u8]) -> Result<ChaCha20Poly1305> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(self.0.as_bytes(), salt, &mut key)
+            .map_err(|e| report!("Cannot derive the cookie key: {}", e))?;
+        Ok(ChaCha20Poly1305::new(&key.into()))
+    }
+
+    pub fn encrypt(&self, plain: &str) -> Result<String> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let sealed = self
+            .cipher(&salt)?
+            .encrypt(Nonce::from_slice(&nonce), plain.as_bytes())
+            .map_err(|_| report!("Cannot encrypt the cookies"))?;
+
+        let data = [&salt[..], &nonce, &sealed].concat();
+        Ok(format!("{HEADER}\n{}\n", BASE64_STANDARD.encode(data)))
+    }
+
+    pub fn decrypt(&self, encrypted: &str) -> Result<String> {
+        let Some(body) = encrypted.strip_prefix(HEADER) else {
+            bail!("Not an encrypted cookie file");
+        };
+        let data = BASE64_STANDARD.decode(body.trim())?;
+        if data.len() < SALT_LEN + NONCE_LEN {
+            bail!("The encrypted cookie file is truncated");
+        }
+        let (salt, rest) = data.split_at(SALT_LEN);
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+
+        let plain = self
+            .cipher(salt)?
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .map_err(|_| report!("Wrong cookie key, or the cookie file is damaged"))?;
+        Ok(String::from_utf8(plain)?)
+    }
+}
+
+pub fn is_encrypted(contents: &str) -> bool {
+    contents.starts_with(HEADER)
+}
+
+/// Reads a file, refusing it if anyone but its owner has access.
+pub async fn read_private(file: &Path) -> Result<String> {
+    let mode = tokio::fs::metadata(file).await?.permissions().mode();
+    if mode & 0o077 != 0 {
+        bail!(
+            "{} is open to other users (mode {:o}), run `chmod 600` on it first",
+            file.to_string_lossy(),
+            mode & 0o777
+        );
+    }
+    Ok(tokio::fs::read_to_string(file).await?)
+}
+
+/// Writes a file only its owner can read, tightening an existing one.
+pub async fn write_private(file: &Path, contents: &str) -> Result<()> {
+    let mut f = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(file)
+        .await?;
+    f.set_permissions(std::fs::Permissions::from_mode(0o600))
+        .await?;
+    f.write_all(contents.as_bytes()).await?;
+    f.flush().await?;
+    Ok(())
+}
+
+#[test]
+fn test_encrypt_cookies() {
+    let secret = Secret("correct horse".to_string());
+    let encrypted = secret.encrypt("[cookies]\n").unwrap();
+    assert!(is_encrypted(&encrypted));
+    assert_eq!(secret.decrypt(&encrypted).unwrap(), "[cookies]\n");
+    assert!(Secret("wrong".to_string()).decrypt(&encrypted).is_err());
+}
+
+#[test]
+fn test_parse_credentials() {
+    let found = Credentials::parse("hunter2\nlogin: writer\nurl: ao3.org\n", None).unwrap();
+    assert_eq!(found.username, "writer");
+    assert_eq!(found.password.expose(), "hunter2");
+
+    let given = Credentials::parse("hunter2\nlogin: writer\n", Some("other")).unwrap();
+    assert_eq!(given.username, "other");
+    assert!(Credentials::parse("hunter2\n", None).is_none());
+}
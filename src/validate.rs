@@ -0,0 +1,391 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use indexmap::IndexSet;
+use toml::de::{DeTable, DeValue};
+
+use crate::{
+    Result,
+    config::{Fanfiction, FicDetails, FicMeta},
+    eprintln_async,
+    exit::Failure,
+    forms::model::ArchiveWarning,
+    render::Diagnostic,
+};
+
+pub const MAX_TITLE: usize = 255;
+pub const MAX_SUMMARY: usize = 1250;
+pub const MAX_NOTES: usize = 5000;
+pub const MAX_TAGS: usize = 75;
+pub const MAX_TAG_LENGTH: usize = 150;
+
+/// Characters AO3 refuses in tag names; commas would split the tag in two.
+pub const TAG_FORBIDDEN: &[char] = &[',', '^', '*', '<', '>', '{', '}', '=', '`', '\\', '%'];
+
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub file: PathBuf,
+    pub diagnostic: Diagnostic,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file.to_string_lossy(), self.diagnostic)
+    }
+}
+
+/// Checks a fic spec against AO3's rules, without touching the network.
+///
+/// Returns the parsed spec if it parses at all, and every problem found.
+pub async fn check_spec(path: &Path) -> Result<(Option<Fanfiction>, Vec<Problem>)> {
+    let text = tokio::fs::read_to_string(path).await?;
+    let spec = Spec {
+        path,
+        text: &text,
+        root: DeTable::parse(&text).ok(),
+    };
+
+    let mut fic: Fanfiction = match toml::from_str(&text) {
+        Ok(fic) => fic,
+        Err(e) => {
+            let offset = e.span().map(|s| s.start).unwrap_or_default();
+            return Ok((None, vec![spec.problem_at(offset, e.message())]));
+        }
+    };
+    fic.path = path.to_path_buf();
+
+    let mut problems = vec![];
+    spec.check(&fic, &mut problems).await;
+    problems.sort_by_key(|p| (p.file.clone(), p.diagnostic.line, p.diagnostic.column));
+    Ok((Some(fic), problems))
+}
+
+/// Loads a spec for posting, refusing it if `check_spec` finds any problem.
+pub async fn load_checked(path: &Path) -> Result<Fanfiction> {
+    let (fic, problems) = check_spec(path).await?;
+    for problem in &problems {
+        eprintln_async!("{}", problem);
+    }
+    match fic {
+        Some(fic) if problems.is_empty() => Ok(fic),
+        _ => Err(Failure::InvalidSpec.report(format!(
+            "{} problem(s) found in {:?}",
+            problems.len(),
+            path
+        ))),
+    }
+}
+
+struct Spec<'a> {
+    path: &'a Path,
+    text: &'a str,
+    root: Option<toml::Spanned<DeTable<'a>>>,
+}
+
+impl Spec<'_> {
+    fn problem_at(&self, offset: usize, message: impl Into<String>) -> Problem {
+        Problem {
+            file: self.path.to_path_buf(),
+            diagnostic: Diagnostic::at(self.text, offset, message),
+        }
+    }
+
+    /// Points at the deepest part of `keys` present in the spec.
+    fn problem(&self, keys: &[&str], message: impl Into<String>) -> Problem {
+        let mut offset = 0;
+        if let Some(root) = &self.root {
+            let mut table = Some(root.get_ref());
+            for key in keys {
+                let Some(value) = table
+                    .and_then(|t| t.iter().find(|(k, _)| k.get_ref() == key))
+                    .map(|(_, v)| v)
+                else {
+                    break;
+                };
+                offset = value.span().start;
+                table = match value.get_ref() {
+                    DeValue::Table(t) => Some(t),
+                    _ => None,
+                };
+            }
+        }
+        self.problem_at(offset, message)
+    }
+
+    async fn check(&self, fic: &Fanfiction, problems: &mut Vec<Problem>) {
+        self.check_details(&["fic"], &fic.fic, problems);
+        if fic.fic.title.trim().is_empty() {
+            problems.push(self.problem(&["fic", "title"], "The work needs a title"));
+        }
+
+        self.check_tags(fic, problems);
+        self.check_meta(fic, problems);
+
+        for (key, chapter) in &fic.chapters {
+            self.check_details(&["chapters", key], chapter, problems);
+        }
+
+        let mut files = vec![];
+        if let Some(file) = &fic.fic.file {
+            files.push((vec!["fic", "file"], file));
+        }
+        for (key, chapter) in &fic.chapters {
+            if let Some(file) = &chapter.file {
+                files.push((vec!["chapters", key.as_str(), "file"], file));
+            }
+        }
+        if files.is_empty() {
+            problems.push(self.problem(&["fic"], "No text file given for the work"));
+        }
+        for (keys, file) in files {
+            self.check_file(fic, &keys, file, problems).await;
+        }
+    }
+
+    fn check_details(&self, at: &[&str], details: &FicDetails, problems: &mut Vec<Problem>) {
+        let mut limit = |key: &str, text: Option<&str>, max: usize, what: &str| {
+            let len = text.map(|t| t.chars().count()).unwrap_or_default();
+            if len > max {
+                let keys: Vec<&str> = at.iter().copied().chain([key]).collect();
+                problems.push(self.problem(
+                    &keys,
+                    format!("{what} is {len} characters long, AO3 allows {max}"),
+                ));
+            }
+        };
+        limit("title", Some(&details.title), MAX_TITLE, "Title");
+        limit(
+            "summary",
+            details.summary.as_deref(),
+            MAX_SUMMARY,
+            "Summary",
+        );
+        limit(
+            "start_note",
+            details.start_note.as_deref(),
+            MAX_NOTES,
+            "Beginning note",
+        );
+        limit(
+            "end_note",
+            details.end_note.as_deref(),
+            MAX_NOTES,
+            "End note",
+        );
+    }
+
+    fn check_tags(&self, fic: &Fanfiction, problems: &mut Vec<Problem>) {
+        let tags = &fic.tags;
+
+        if tags.fandoms.is_empty() {
+            problems.push(self.problem(&["tags", "fandoms"], "At least one fandom is required"));
+        }
+
+        let warnings = &tags.warnings;
+        if warnings.contains(&ArchiveWarning::NA) && warnings.len() > 1 {
+            problems.push(self.problem(
+                &["tags", "warnings"],
+                "\"No Archive Warnings Apply\" cannot be combined with other warnings",
+            ));
+        }
+
+        let kinds: [(&str, &IndexSet<String>); 4] = [
+            ("fandoms", &tags.fandoms),
+            ("relationships", &tags.relationships),
+            ("characters", &tags.characters),
+            ("other", &tags.other),
+        ];
+
+        let total: usize = kinds.iter().map(|(_, t)| t.len()).sum();
+        if total > MAX_TAGS {
+            problems.push(self.problem(
+                &["tags"],
+                format!(
+                    "{total} fandom, relationship, character and other tags, AO3 allows {MAX_TAGS}"
+                ),
+            ));
+        }
+
+        for (key, set) in kinds {
+            for tag in set {
+                let len = tag.chars().count();
+                if len > MAX_TAG_LENGTH {
+                    problems.push(self.problem(
+                        &["tags", key],
+                        format!(
+                            "Tag {tag:?} is {len} characters long, AO3 allows {MAX_TAG_LENGTH}"
+                        ),
+                    ));
+                }
+                if let Some(c) = tag.chars().find(|c| TAG_FORBIDDEN.contains(c)) {
+                    problems.push(self.problem(
+                        &["tags", key],
+                        format!("Tag {tag:?} contains {c:?}, which AO3 does not allow in tags"),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn check_meta(&self, fic: &Fanfiction, problems: &mut Vec<Problem>) {
+        let meta = &fic.meta;
+
+        if meta.language.trim().is_empty() {
+            problems.push(self.problem(&["meta", "language"], "A language is required"));
+        }
+
+        let chapters = fic.chapters.len() as u64;
+        if meta.total_chapters != 0 && chapters > meta.total_chapters {
+            problems.push(self.problem(
+                &["meta", "total_chapters"],
+                format!(
+                    "total_chapters is {} but {} chapters are listed",
+                    meta.total_chapters, chapters
+                ),
+            ));
+        }
+
+        if let Some(date) = &meta.publication_date
+            && FicMeta::parse_date(date).is_none()
+        {
+            problems.push(self.problem(
+                &["meta", "publication_date"],
+                format!("Malformed publication date {date:?}, expected YYYY-MM-DD"),
+            ));
+        }
+
+        if let Some(remix) = &fic.remix
+            && remix.url.trim().is_empty()
+        {
+            problems.push(self.problem(&["remix", "url"], "A remix needs the URL of the original"));
+        }
+    }
+
+    async fn check_file(
+        &self,
+        fic: &Fanfiction,
+        keys: &[&str],
+        file: &Path,
+        problems: &mut Vec<Problem>,
+    ) {
+        let resolved = fic.resolve_file(file);
+        let text = match tokio::fs::read_to_string(&resolved).await {
+            Ok(text) => text,
+            Err(e) => {
+                problems.push(self.problem(
+                    keys,
+                    format!("Cannot read {}: {}", resolved.to_string_lossy(), e),
+                ));
+                return;
+            }
+        };
+        if let Err(diagnostics) = fic.meta.format.render(&text) {
+            problems.extend(diagnostics.into_iter().map(|diagnostic| Problem {
+                file: resolved.clone(),
+                diagnostic,
+            }));
+        }
+    }
+}
+
+/// Writes `fic` and its text next to each other in a fresh temporary
+/// directory, and returns the messages `check_spec` gives for it.
+#[cfg(test)]
+async fn check_messages(name: &str, fic: &Fanfiction) -> Vec<String> {
+    let dir = std::env::temp_dir().join(format!("ficwright-{}-{name}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    tokio::fs::write(dir.join("text.md"), "Some *text*.\n")
+        .await
+        .unwrap();
+    let path = dir.join("fic.toml");
+    tokio::fs::write(&path, toml::to_string_pretty(fic).unwrap())
+        .await
+        .unwrap();
+
+    let (_, problems) = check_spec(&path).await.unwrap();
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+    problems.into_iter().map(|p| p.diagnostic.message).collect()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_check_spec() {
+    let mut valid = Fanfiction::default();
+    valid.fic.title = "Title".to_string();
+    valid.fic.file = Some("text.md".into());
+    valid.tags.fandoms.insert("Fandom".to_string());
+    valid.meta.language = "English".to_string();
+    assert_eq!(check_messages("valid", &valid).await, Vec::<String>::new());
+
+    let mut fic = valid.clone();
+    fic.fic.title = "x".repeat(MAX_TITLE + 1);
+    assert_eq!(
+        check_messages("title", &fic).await,
+        ["Title is 256 characters long, AO3 allows 255"]
+    );
+
+    let mut fic = valid.clone();
+    fic.fic.summary = Some("x".repeat(MAX_SUMMARY + 1));
+    assert_eq!(
+        check_messages("summary", &fic).await,
+        ["Summary is 1251 characters long, AO3 allows 1250"]
+    );
+
+    let mut fic = valid.clone();
+    fic.fic.end_note = Some("x".repeat(MAX_NOTES + 1));
+    assert_eq!(
+        check_messages("notes", &fic).await,
+        ["End note is 5001 characters long, AO3 allows 5000"]
+    );
+
+    let mut fic = valid.clone();
+    fic.tags.other = (0..MAX_TAGS).map(|i| format!("Tag {i}")).collect();
+    assert_eq!(
+        check_messages("tag-count", &fic).await,
+        ["76 fandom, relationship, character and other tags, AO3 allows 75"]
+    );
+
+    let mut fic = valid.clone();
+    fic.tags.warnings = [ArchiveWarning::NA, ArchiveWarning::Violence].into();
+    assert_eq!(
+        check_messages("warnings", &fic).await,
+        ["\"No Archive Warnings Apply\" cannot be combined with other warnings"]
+    );
+
+    let mut fic = valid.clone();
+    fic.tags.fandoms.clear();
+    assert_eq!(
+        check_messages("fandoms", &fic).await,
+        ["At least one fandom is required"]
+    );
+
+    let mut fic = valid.clone();
+    fic.meta.total_chapters = 1;
+    for key in ["01", "02"] {
+        let chapter = FicDetails {
+            file: Some("text.md".into()),
+            ..Default::default()
+        };
+        fic.chapters.insert(key.to_string(), chapter);
+    }
+    assert_eq!(
+        check_messages("chapters", &fic).await,
+        ["total_chapters is 1 but 2 chapters are listed"]
+    );
+
+    let mut fic = valid.clone();
+    fic.meta.publication_date = Some("2024-02-30".to_string());
+    assert_eq!(
+        check_messages("date", &fic).await,
+        ["Malformed publication date \"2024-02-30\", expected YYYY-MM-DD"]
+    );
+
+    let mut fic = valid.clone();
+    fic.fic.file = Some("missing.md".into());
+    let messages = check_messages("file", &fic).await;
+    assert!(
+        messages.len() == 1 && messages[0].starts_with("Cannot read "),
+        "{messages:?}"
+    );
+}